@@ -0,0 +1,164 @@
+//! Bell 202 AFSK (1200 baud, 1200/2200 Hz) demodulator, i.e. what a TNC does
+//! with the audio coming out of a radio
+
+use crate::hdlc;
+
+pub const BAUD: f64 = 1200.;
+pub const MARK_HZ: f64 = 1200.;
+pub const SPACE_HZ: f64 = 2200.;
+
+/// How much of a phase error a signal transition corrects, the rest is the
+/// clock's inertia
+const PLL_INERTIA: f64 = 0.75;
+
+/// Correlates signal with a tone over one bit worth of samples
+#[derive(Debug)]
+struct Correlator {
+    phase: f64,
+    dphase: f64,
+    i: SlidingSum,
+    q: SlidingSum,
+}
+
+impl Correlator {
+    fn new(freq_hz: f64, sample_rate: u32, window: usize) -> Self {
+        Self {
+            phase: 0.,
+            dphase: 2. * std::f64::consts::PI * freq_hz / sample_rate as f64,
+            i: SlidingSum::new(window),
+            q: SlidingSum::new(window),
+        }
+    }
+
+    /// Returns tone energy in the window ending with this sample
+    fn push(&mut self, sample: f64) -> f64 {
+        let i = self.i.push(sample * self.phase.cos());
+        let q = self.q.push(sample * self.phase.sin());
+        self.phase = (self.phase + self.dphase) % (2. * std::f64::consts::PI);
+        i * i + q * q
+    }
+}
+
+#[derive(Debug)]
+struct SlidingSum {
+    buf: Vec<f64>,
+    pos: usize,
+    sum: f64,
+}
+
+impl SlidingSum {
+    fn new(len: usize) -> Self {
+        Self {
+            buf: vec![0.; len.max(1)],
+            pos: 0,
+            sum: 0.,
+        }
+    }
+
+    fn push(&mut self, v: f64) -> f64 {
+        self.sum += v - self.buf[self.pos];
+        self.buf[self.pos] = v;
+        self.pos = (self.pos + 1) % self.buf.len();
+        self.sum
+    }
+}
+
+/// Turns 16-bit mono audio samples into AX.25 frames (without FCS)
+#[derive(Debug)]
+pub struct Demodulator {
+    mark: Correlator,
+    space: Correlator,
+    /// Tone currently heard, true for mark
+    level: bool,
+    /// Bit clock, samples a bit when it wraps around; signal transitions
+    /// nudge it towards zero, i.e. half a bit away from sampling
+    pll: i32,
+    pll_step: i32,
+    /// Tone at the previous bit, for NRZI decoding
+    prev_bit_level: bool,
+    hdlc: hdlc::Decoder,
+}
+
+impl Demodulator {
+    pub fn new(sample_rate: u32) -> Self {
+        let window = (sample_rate as f64 / BAUD).round() as usize;
+        Self {
+            mark: Correlator::new(MARK_HZ, sample_rate, window),
+            space: Correlator::new(SPACE_HZ, sample_rate, window),
+            level: false,
+            pll: 0,
+            pll_step: (BAUD / sample_rate as f64 * 2f64.powi(32)) as u32 as i32,
+            prev_bit_level: false,
+            hdlc: hdlc::Decoder::new(),
+        }
+    }
+
+    pub fn push(&mut self, samples: &[i16]) -> Vec<Vec<u8>> {
+        let mut res = vec![];
+        for &sample in samples {
+            let sample = sample as f64 / i16::MAX as f64;
+            let level = self.mark.push(sample) > self.space.push(sample);
+
+            let prev_pll = self.pll;
+            self.pll = self.pll.wrapping_add(self.pll_step);
+            if prev_pll > 0 && self.pll < 0 {
+                // NRZI: no change in tone is a one, a change is a zero
+                let bit = level == self.prev_bit_level;
+                self.prev_bit_level = level;
+                res.extend(self.hdlc.push(bit));
+            }
+            if level != self.level {
+                self.pll = (self.pll as f64 * PLL_INERTIA) as i32;
+                self.level = level;
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::ax25;
+
+    /// Returns AFSK audio for the frame, as a TNC would transmit it
+    pub fn modulate(frame: &[u8], sample_rate: u32) -> Vec<i16> {
+        let mut res = vec![];
+        let (mut phase, mut level, mut t) = (0f64, true, 0f64);
+        let samples_per_bit = sample_rate as f64 / BAUD;
+        for bit in hdlc::encode(frame, 16) {
+            if !bit {
+                level = !level;
+            }
+            let freq = if level { MARK_HZ } else { SPACE_HZ };
+            t += samples_per_bit;
+            while (res.len() as f64) < t {
+                res.push((phase.sin() * 16000.) as i16);
+                phase += 2. * std::f64::consts::PI * freq / sample_rate as f64;
+            }
+        }
+        res
+    }
+
+    #[test]
+    fn test_demodulate() {
+        let frame = ax25::Frame::from_tnc2("N0CALL-9>APDW16,WIDE1-1:!4046.40N/11912.12W>Hi")
+            .unwrap()
+            .encode();
+        for sample_rate in [8000, 22050, 44100, 48000] {
+            let mut audio = vec![0i16; 1000];
+            audio.extend(modulate(&frame, sample_rate));
+            audio.extend(vec![0i16; 1000]);
+            // Cheap noise that doesn't need a random number generator
+            for (i, v) in audio.iter_mut().enumerate() {
+                *v = v.saturating_add(((i * 7919) % 4001) as i16 - 2000);
+            }
+            let mut demod = Demodulator::new(sample_rate);
+            let frames = audio
+                .chunks(333)
+                .flat_map(|chunk| demod.push(chunk))
+                .collect::<Vec<_>>();
+            assert_eq!(frames, vec![frame.clone()], "at {} Hz", sample_rate);
+        }
+    }
+}
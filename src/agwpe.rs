@@ -0,0 +1,84 @@
+//! AGWPE TCP/IP API framing, as spoken by Dire Wolf and SoundModem on port
+//! 8000, see https://www.on7lds.net/42/sites/default/files/AGWPEAPI.HTM
+
+pub const HEADER_LEN: usize = 36;
+
+/// Ask TNC to send us raw AX.25 frames
+pub const KIND_RAW_ON: u8 = b'k';
+/// Raw AX.25 frame, prefixed with one KISS type byte
+pub const KIND_RAW: u8 = b'K';
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub port: u8,
+    pub kind: u8,
+    pub pid: u8,
+    pub call_from: String,
+    pub call_to: String,
+    pub data_len: u32,
+}
+
+impl Header {
+    pub fn new(port: u8, kind: u8) -> Self {
+        Self {
+            port,
+            kind,
+            pid: 0,
+            call_from: String::new(),
+            call_to: String::new(),
+            data_len: 0,
+        }
+    }
+
+    pub fn decode(bytes: &[u8; HEADER_LEN]) -> Self {
+        Self {
+            port: bytes[0],
+            kind: bytes[4],
+            pid: bytes[6],
+            call_from: decode_call(&bytes[8..18]),
+            call_to: decode_call(&bytes[18..28]),
+            data_len: u32::from_le_bytes([bytes[28], bytes[29], bytes[30], bytes[31]]),
+        }
+    }
+
+    pub fn encode(&self) -> [u8; HEADER_LEN] {
+        let mut res = [0u8; HEADER_LEN];
+        res[0] = self.port;
+        res[4] = self.kind;
+        res[6] = self.pid;
+        encode_call(&self.call_from, &mut res[8..18]);
+        encode_call(&self.call_to, &mut res[18..28]);
+        res[28..32].copy_from_slice(&self.data_len.to_le_bytes());
+        res
+    }
+}
+
+fn decode_call(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+fn encode_call(call: &str, buf: &mut [u8]) {
+    // Null-terminated, so one byte shorter than the field
+    let n = call.len().min(buf.len() - 1);
+    buf[..n].copy_from_slice(&call.as_bytes()[..n]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_roundtrip() {
+        let hdr = Header {
+            data_len: 300,
+            call_from: "N0CALL-10".into(),
+            ..Header::new(1, KIND_RAW)
+        };
+        let bytes = hdr.encode();
+        assert_eq!(bytes[0], 1);
+        assert_eq!(bytes[4], b'K');
+        assert_eq!(&bytes[28..32], &[0x2c, 0x01, 0, 0]);
+        assert_eq!(Header::decode(&bytes), hdr);
+    }
+}
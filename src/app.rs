@@ -1,56 +1,287 @@
 use crate::{
-    aprs_is, aprs_tty,
+    aprs_audio, aprs_is, aprs_is_server, aprs_replay, aprs_sim, aprs_tcp, aprs_tty, beacon,
     err::{Error, LogResult, Result},
-    io,
-    svc::jsonlog::JsonLog,
-    util::time::{Timespan, Timestamp},
+    igate,
+    io::{self, store::Fsync},
+    svc::{
+        jsonlog::{JsonLog, Rotation},
+        sqlite::SqliteStore,
+    },
+    util::{
+        sync::recv_if_some,
+        time::{Timespan, Timestamp},
+    },
     webapi,
 };
 use std::collections::HashMap;
-use tokio::sync::mpsc;
+use tokio::sync::{broadcast, mpsc};
 
+mod answer_query;
 mod get_view;
 mod post_aprs;
 mod print_ttys;
+mod send_beacon;
+mod send_message;
 
 pub use print_ttys::*;
 
+/// Source of the packets we send ourselves
+pub static OWN_SOURCE: &str = "self";
+
+/// How far ahead of ours the clocks of other machines can be
+const CLOCK_SKEW: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// Where APRS packets come from; any combination of these can be used
+#[derive(Debug, Clone, Default)]
+pub struct Inputs {
+    pub tty: Option<String>,
+    pub baudrate: Option<u16>,
+    pub kiss: bool,
+    pub aprsis_servers: Vec<String>,
+    pub callsign: String,
+    pub aprsis_radius_km: f64,
+    pub aprsis_prefixes: Vec<String>,
+    pub kiss_server: Option<String>,
+    pub agwpe_server: Option<String>,
+    /// Audio to demodulate, `-` for stdin
+    pub audio: Option<String>,
+    pub audio_rate: u32,
+    /// Event log to play back
+    pub replay: Option<std::path::PathBuf>,
+    pub replay_speed: f64,
+    /// Longest pause between replayed packets
+    pub replay_max_pause: std::time::Duration,
+    /// Number of made-up stations to simulate
+    pub sim: usize,
+    /// Forward packets heard on RF to APRS-IS
+    pub igate: bool,
+    /// Answer queries messaged to our callsign
+    pub answer_queries: bool,
+}
+
+/// Where and how we keep received packets
+#[derive(Debug, Clone, Default)]
+pub struct EventLog {
+    pub backend: Backend,
+    pub path: Option<std::path::PathBuf>,
+    /// Segments and retention, JSON lines only
+    pub rotation: Rotation,
+    pub fsync: Fsync,
+    /// How long to keep records for, if not forever
+    pub max_age: Option<std::time::Duration>,
+}
+
+/// Kind of event store
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// Text file with a JSON record per line, easy to grep and copy around
+    #[default]
+    JsonLines,
+    /// SQLite database, indexed by time and callsign
+    Sqlite,
+}
+
+impl std::str::FromStr for Backend {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "jsonl" => Ok(Self::JsonLines),
+            "sqlite" => Ok(Self::Sqlite),
+            _ => Err(Error::BadRequest(format!(
+                "bad store {:?}, expected jsonl or sqlite",
+                s
+            ))),
+        }
+    }
+}
+
 pub async fn run(
     http_port: u16,
+    aprsis_port: Option<u16>,
     www_root: Option<std::path::PathBuf>,
-    tty: Option<String>,
-    baudrate: Option<u16>,
-    aprsis_server: Option<String>,
-    eventlog: Option<std::path::PathBuf>,
+    inputs: Inputs,
+    beacon: Option<beacon::Config>,
+    eventlog: EventLog,
 ) -> Result<()> {
     let mut tasks = tokio::task::JoinSet::new();
 
+    // Our own packets go out through the first KISS TNC we have
+    let (mut tty_outgoing, mut kiss_outgoing, mut tnc_tx) = (None, None, None);
+    let callsign = inputs.callsign.to_ascii_uppercase();
+    let has_tnc = inputs.tty.is_some() && inputs.kiss || inputs.kiss_server.is_some();
+    if callsign != aprs_is::DEFAULT_CALLSIGN && has_tnc {
+        let (tx, rx) = mpsc::channel::<String>(64);
+        if inputs.tty.is_some() && inputs.kiss {
+            tty_outgoing = Some(rx);
+        } else {
+            kiss_outgoing = Some(rx);
+        }
+        tnc_tx = Some(tx);
+    } else if beacon.is_some() {
+        return Err(Error::MissingRequiredArgument(
+            "callsign and kiss or kisstcp".into(),
+        ));
+    }
+
     // Create store
-    let eventlog = eventlog.unwrap_or("/tmp/lpkiosk-events.log".into());
-    let store = JsonLog::<io::store::Record>::new(eventlog);
+    let default_path = match eventlog.backend {
+        Backend::JsonLines => "/tmp/lpkiosk-events.log",
+        Backend::Sqlite => "/tmp/lpkiosk-events.db",
+    };
+    let path = eventlog.path.unwrap_or(default_path.into());
+    if inputs.replay.as_ref() == Some(&path) {
+        // We'd be replaying what we've just replayed, forever
+        return Err(Error::Other(
+            "can't replay the event log we write to, copy it first".into(),
+        ));
+    }
+    let rotation = &eventlog.rotation;
+    if eventlog.backend == Backend::Sqlite
+        && (rotation.daily
+            || rotation.max_segment_bytes.is_some()
+            || rotation.max_total_bytes.is_some())
+    {
+        // Nothing to rotate, and keeping records by size isn't done there
+        return Err(Error::BadRequest(
+            "--eventlog-daily, --eventlog-segment-mb and --eventlog-max-mb only work with --store jsonl"
+                .into(),
+        ));
+    }
+    let store: Box<dyn io::store::Store> = match eventlog.backend {
+        Backend::JsonLines => {
+            let log = JsonLog::<io::store::Record>::new(path)
+                .with_rotation(eventlog.rotation)
+                .with_fsync(eventlog.fsync);
+            // Preloading reads it before anything gets written
+            log.repair_tail().await?;
+            Box::new(log)
+        }
+        Backend::Sqlite => Box::new(SqliteStore::open(path, eventlog.fsync)?),
+    };
 
     // Create I/O channels
-    let (aprs_dta_tx, aprs_dta_rx) = mpsc::channel::<String>(1024);
+    let (aprs_dta_tx, aprs_dta_rx) = mpsc::channel::<io::aprs::Received>(1024);
     let (user_evt_tx, user_evt_rx) = mpsc::channel::<io::user::Event>(1024);
+    let (demo_dta_tx, demo_dta_rx) = mpsc::channel::<io::aprs::Received>(1024);
 
     // Spawn service tasks
-    tasks.spawn(Server::new(user_evt_rx, aprs_dta_rx, store).run());
+    let mut server = Server::new(user_evt_rx, aprs_dta_rx, store).with_demo_input(demo_dta_rx);
+    if let Some(tnc_tx) = tnc_tx {
+        server = server.with_transmitter(callsign.clone(), tnc_tx);
+        if inputs.answer_queries {
+            server = server.with_query_answers();
+        }
+    } else if inputs.answer_queries {
+        return Err(Error::MissingRequiredArgument(
+            "callsign and kiss or kisstcp".into(),
+        ));
+    }
+    if let Some(beacon) = beacon {
+        server = server.with_beacon(beacon);
+    }
+    if let Some(max_age) = eventlog.max_age {
+        server = server.with_retention(max_age);
+    }
+    if let Some(port) = aprsis_port {
+        let (feed_tx, _) = broadcast::channel::<String>(aprs_is_server::FEED_CAPACITY);
+        server = server.with_feed(feed_tx.clone());
+        tasks.spawn(aprs_is_server::serve(port, callsign.clone(), feed_tx));
+    }
+    let city = server.brc.clone();
+    tasks.spawn(server.run());
+
+    // IGate sits between RF inputs and the server
+    let mut aprsis_uplink = None;
+    let rf_dta_tx = if inputs.igate {
+        if inputs.aprsis_servers.is_empty() {
+            return Err(Error::MissingRequiredArgument("aprsis".into()));
+        }
+        if callsign == aprs_is::DEFAULT_CALLSIGN {
+            return Err(Error::MissingRequiredArgument("callsign".into()));
+        }
+        let (rf_dta_tx, rf_dta_rx) = mpsc::channel::<io::aprs::Received>(1024);
+        let (uplink_tx, uplink_rx) = mpsc::channel::<String>(1024);
+        tasks.spawn(igate::run(
+            callsign.clone(),
+            rf_dta_rx,
+            aprs_dta_tx.clone(),
+            uplink_tx,
+        ));
+        aprsis_uplink = Some(uplink_rx);
+        rf_dta_tx
+    } else {
+        aprs_dta_tx.clone()
+    };
 
     // APRS TTY
-    if let Some(tty) = tty {
+    if let Some(tty) = inputs.tty {
         tasks.spawn(aprs_tty::read(
             tty,
-            baudrate.unwrap_or(9600) as u32,
-            aprs_dta_tx.clone(),
+            inputs.baudrate.unwrap_or(9600) as u32,
+            inputs.kiss,
+            tty_outgoing,
+            rf_dta_tx.clone(),
         ));
     }
 
     // APRS IS
-    if let Some(server) =aprsis_server {
-        tasks.spawn(aprs_is::read(
+    if !inputs.aprsis_servers.is_empty() {
+        let mut config = aprs_is::Config::new(
+            inputs.aprsis_servers,
+            callsign.clone(),
+            crate::brc2023::get().center(),
+        );
+        config.radius_km = inputs.aprsis_radius_km;
+        config.buddies = crate::brc2023::POIS
+            .iter()
+            .map(|poi| poi.call.to_string())
+            .collect();
+        config.prefixes = inputs.aprsis_prefixes;
+        tasks.spawn(aprs_is::read(config, aprsis_uplink, aprs_dta_tx.clone()));
+    }
+
+    // Software TNC, e.g. Dire Wolf
+    if let Some(server) = inputs.kiss_server {
+        tasks.spawn(aprs_tcp::read(
             server,
-            aprs_dta_tx.clone(),
-        ));    
+            aprs_tcp::Protocol::Kiss,
+            kiss_outgoing,
+            rf_dta_tx.clone(),
+        ));
+    }
+    if let Some(server) = inputs.agwpe_server {
+        tasks.spawn(aprs_tcp::read(
+            server,
+            aprs_tcp::Protocol::Agwpe,
+            None,
+            rf_dta_tx.clone(),
+        ));
+    }
+
+    // Radio audio, no TNC needed
+    if let Some(source) = inputs.audio {
+        tasks.spawn(aprs_audio::read(
+            source,
+            inputs.audio_rate,
+            rf_dta_tx.clone(),
+        ));
+    }
+
+    // Recorded packets are for show only, we don't want to IGate, store or
+    // answer them again
+    if let Some(path) = inputs.replay {
+        tasks.spawn(aprs_replay::read(
+            path,
+            inputs.replay_speed,
+            inputs.replay_max_pause,
+            demo_dta_tx.clone(),
+        ));
+    }
+
+    // Made-up traffic, for show only too
+    if inputs.sim > 0 {
+        tasks.spawn(aprs_sim::read(city, inputs.sim, demo_dta_tx.clone()));
     }
 
     // Actix handles its own shutdown and we'll piggy back on that (also, it
@@ -72,16 +303,35 @@ pub struct Server {
     pois_by_call: HashMap<&'static str, &'static io::site::Poi>,
 
     user_evt_rx: mpsc::Receiver<io::user::Event>,
-    aprs_dta_rx: mpsc::Receiver<String>,
+    aprs_dta_rx: mpsc::Receiver<io::aprs::Received>,
+    /// Packets to show but not store or react to, e.g. replayed ones
+    demo_dta_rx: Option<mpsc::Receiver<io::aprs::Received>>,
+
+    store: Box<dyn io::store::Store>,
+    /// How long to keep stored records for
+    retention: Option<std::time::Duration>,
 
-    store: JsonLog<io::store::Record>,
+    callsign: Option<String>,
+    tnc_tx: Option<mpsc::Sender<String>>,
+    beacon: Option<beacon::Config>,
+    outbox: crate::aprs::Outbox,
+    /// Answer queries messaged to us, not just ack them
+    answer_queries: bool,
+    /// Messages we've acked (and answered) lately by sender and msgno, so
+    /// that retries and copies via other paths get just an ack
+    answered: HashMap<(String, String), Timestamp>,
+    /// Log last rebuilt for playback and the time it's as of, so that
+    /// clients polling as they play get it moved along rather than rebuilt
+    playback: Option<(Timestamp, crate::aprs::Log)>,
+    /// Everything we ingest, for local APRS-IS clients
+    feed_tx: Option<broadcast::Sender<String>>,
 }
 
 impl Server {
     pub fn new(
         user_evt_rx: mpsc::Receiver<io::user::Event>,
-        aprs_dta_rx: mpsc::Receiver<String>,
-        store: JsonLog<io::store::Record>,
+        aprs_dta_rx: mpsc::Receiver<io::aprs::Received>,
+        store: Box<dyn io::store::Store>,
     ) -> Self {
         let brc = crate::brc2023::get();
         let aprs_cache = crate::aprs::Log::new();
@@ -95,55 +345,169 @@ impl Server {
             pois_by_call,
             user_evt_rx,
             aprs_dta_rx,
+            demo_dta_rx: None,
             store,
+            retention: None,
+            callsign: None,
+            tnc_tx: None,
+            beacon: None,
+            outbox: crate::aprs::Outbox::new(),
+            answer_queries: false,
+            answered: HashMap::new(),
+            playback: None,
+            feed_tx: None,
         }
     }
 
+    /// Lets server send packets (beacons, messages, acks) through the TNC
+    pub fn with_transmitter(mut self, callsign: String, tnc_tx: mpsc::Sender<String>) -> Self {
+        self.aprs_cache.set_own_callsign(callsign.clone());
+        self.callsign = Some(callsign);
+        self.tnc_tx = Some(tnc_tx);
+        self
+    }
+
+    /// Lets server answer queries sent to it, see `answer_query`
+    pub fn with_query_answers(mut self) -> Self {
+        self.answer_queries = true;
+        self
+    }
+
+    /// Adds input for packets that are only for show, see `process_demo_data`
+    pub fn with_demo_input(mut self, demo_dta_rx: mpsc::Receiver<io::aprs::Received>) -> Self {
+        self.demo_dta_rx = Some(demo_dta_rx);
+        self
+    }
+
+    pub fn with_beacon(mut self, beacon: beacon::Config) -> Self {
+        self.beacon = Some(beacon);
+        self
+    }
+
+    pub fn with_retention(mut self, max_age: std::time::Duration) -> Self {
+        self.retention = Some(max_age);
+        self
+    }
+
+    pub fn with_feed(mut self, feed_tx: broadcast::Sender<String>) -> Self {
+        self.feed_tx = Some(feed_tx);
+        self
+    }
+
     pub async fn run(mut self) -> Result<()> {
         self.preload().await.log_result();
 
         log::debug!("entering server mainloop");
+        let mut housekeeping = tokio::time::interval(std::time::Duration::from_secs(60));
+        let beacon_interval = match &self.beacon {
+            Some(beacon) => beacon.interval,
+            None => std::time::Duration::from_secs(3600),
+        };
+        let mut beacon = tokio::time::interval(beacon_interval);
+        let mut outbox = tokio::time::interval(std::time::Duration::from_secs(5));
+        let mut flush = tokio::time::interval(std::time::Duration::from_secs(1));
+        let mut demo_dta_rx = self.demo_dta_rx.take();
+        let mut demo_dta_rx = demo_dta_rx.as_mut();
         loop {
             tokio::select! {
                 Some(evt) = self.user_evt_rx.recv() => self.process_user_event(evt).await.log_result(),
                 Some(data) = self.aprs_dta_rx.recv() => self.process_aprs_data(data).await.log_result(),
+                Some(data) = recv_if_some(&mut demo_dta_rx) => self.process_demo_data(data).await.log_result(),
+                _ = housekeeping.tick() => self.housekeeping().await.log_result(),
+                _ = flush.tick() => self.store.flush().await.log_result(),
+                _ = beacon.tick(), if self.beacon.is_some() => self.send_beacon().await.log_result(),
+                _ = outbox.tick(), if self.tnc_tx.is_some() => self.send_outbox().await.log_result(),
             }
         }
     }
 
+    pub async fn housekeeping(&mut self) -> Result<()> {
+        let now = Timestamp::now();
+        self.aprs_cache.expire(now);
+        self.expire_answered(now);
+        if let Some(max_age) = self.retention {
+            self.store.compact(now.saturating_sub(max_age)).await?;
+        }
+        Ok(())
+    }
+
     pub async fn process_user_event(&mut self, evt: io::user::Event) -> Result<()> {
         match evt {
-            io::user::Event::ViewRequest(_query, res) => {
-                let view_res = self.view().await;
+            io::user::Event::ViewRequest(query, res) => {
+                let view_res = self.view(&query).await;
                 res.send(view_res).map_err(|_| Error::Disconnected)
             }
+            io::user::Event::SendMessage(req, res) => {
+                let msg_res = self.queue_message(req).await;
+                res.send(msg_res).map_err(|_| Error::Disconnected)
+            }
         }
     }
 
-    pub async fn process_aprs_data(&mut self, data: String) -> Result<()> {
-        let now = Timestamp::now();
-        self.store
-            .write(now, &io::store::Record::AprsPacket { data: data.clone() })
+    pub async fn process_aprs_data(&mut self, rx: io::aprs::Received) -> Result<()> {
+        let data = rx.data.clone();
+        self.record_aprs_data(rx).await?;
+        if self.callsign.is_some() {
+            if let Ok(crate::aprs::Packet::Message(msg)) = crate::aprs::Packet::parse(&data) {
+                let rf = crate::aprs::Heard::new(None, &data).is_rf();
+                self.process_message(msg, rf).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Shows replayed or made-up packet on the map and to APRS-IS clients,
+    /// without storing it or reacting to it, so that it doesn't end up
+    /// transmitted or back in the map after a restart
+    pub async fn process_demo_data(&mut self, rx: io::aprs::Received) -> Result<()> {
+        if let Some(feed_tx) = &self.feed_tx {
+            let _ = feed_tx.send(rx.data.clone());
+        }
+        self.post_aprs(Timestamp::now(), Some(rx.source), rx.data)
             .await
-            .log_result();
-        self.post_aprs(now, data).await
+    }
+
+    /// Stores packet and updates our view of the world, `process_aprs_data`
+    /// minus reacting to it
+    pub async fn record_aprs_data(&mut self, rx: io::aprs::Received) -> Result<()> {
+        let now = Timestamp::now();
+        let heard = crate::aprs::Heard::new(Some(rx.source.clone()), &rx.data);
+        let record = io::store::Record::AprsPacket {
+            data: rx.data.clone(),
+            source: Some(rx.source),
+            digipeater: heard.digipeater().map(|v| v.to_string()),
+            path: heard.path,
+        };
+        self.store.append(now, &record).await.log_result();
+        if let Some(feed_tx) = &self.feed_tx {
+            // Fails only when nobody is listening
+            let _ = feed_tx.send(rx.data.clone());
+        }
+        self.post_aprs(now, heard.source, rx.data).await
     }
 
     pub async fn preload(&mut self) -> Result<()> {
         log::info!("preloading data...");
         let mut cnt = 0;
+        // Records written by a machine whose clock is a bit ahead of ours
+        // still count
         let span = Timespan::week_until_now();
-        match self.store.query(span).await {
-            Ok(records) => {
-                for (ts, rec) in records {
-                    match rec {
-                        io::store::Record::AprsPacket { data } => {
-                            cnt += 1;
-                            self.post_aprs(ts, data).await.log_result();
-                        }
-                    };
+        let span =
+            Timespan::from_two_timestamps(span.start(), span.end().saturating_add(CLOCK_SKEW));
+        match self.store.records(span).await {
+            Ok(mut records) => loop {
+                match records.next().await {
+                    Ok(Some((ts, io::store::Record::AprsPacket { data, source, .. }))) => {
+                        cnt += 1;
+                        self.post_aprs(ts, source, data).await.log_result();
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        Err(e).log_result();
+                        break;
+                    }
                 }
-            }
+            },
             Err(e) => Err(e).log_result(),
         }
         log::info!("preloaded {} items", cnt);
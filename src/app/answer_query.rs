@@ -0,0 +1,273 @@
+use super::*;
+use crate::{aprs, clockpos::ClockPos, util::units::ft2m};
+
+/// How far from the requested address a station can be to be listed as near
+const NEAR_RADIUS_M: f64 = 300.;
+/// Stations not heard from within this long are not listed as near
+const NEAR_MAX_AGE: std::time::Duration = std::time::Duration::from_secs(3600);
+
+const MPH_IN_MPS: f64 = 2.23694;
+
+static HELP: &str = "WHERE <call or name>, NEAR <clock&street> e.g. NEAR 4:30&C, WX";
+
+/// Commands we answer when messaged over APRS
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    /// Last known location of a station, by callsign or POI name
+    Where(String),
+    /// Stations around an address, e.g. `4:30&C` or `9:00&500'`
+    Near(String),
+    /// Latest weather report
+    Wx,
+    Help,
+}
+
+impl Query {
+    /// Returns `None` for anything we don't understand, so we never end up
+    /// chatting with another bot
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        let (cmd, arg) = match text.split_once(char::is_whitespace) {
+            Some((cmd, arg)) => (cmd, arg.trim()),
+            None => (text, ""),
+        };
+        match cmd.to_ascii_uppercase().as_str() {
+            "WHERE" | "W" if !arg.is_empty() => Some(Self::Where(arg.into())),
+            "NEAR" | "N" if !arg.is_empty() => Some(Self::Near(arg.into())),
+            "WX" => Some(Self::Wx),
+            "HELP" | "?" => Some(Self::Help),
+            _ => None,
+        }
+    }
+}
+
+impl Server {
+    /// Answers query sent to us, replies are short enough to fit in a single
+    /// APRS message
+    pub fn answer_query(&self, query: &Query, now: Timestamp) -> String {
+        let answer = match query {
+            Query::Where(name) => self.answer_where(name, now),
+            Query::Near(address) => self.answer_near(address, now),
+            Query::Wx => self.answer_wx(now),
+            Query::Help => HELP.into(),
+        };
+        // Names and comments come from the air, don't let them break the
+        // message format
+        answer
+            .chars()
+            .map(|c| match c {
+                '|' | '~' | '{' => ' ',
+                c if (' '..='~').contains(&c) => c,
+                _ => '?',
+            })
+            .take(aprs::Outbox::MAX_TEXT_LEN)
+            .collect()
+    }
+
+    fn answer_where(&self, name: &str, now: Timestamp) -> String {
+        let callsign = name.to_ascii_uppercase();
+        let poi = self
+            .pois_by_call
+            .get(callsign.as_str())
+            .copied()
+            .or_else(|| {
+                self.pois_by_call.values().copied().find(|poi| {
+                    poi.name.eq_ignore_ascii_case(name) || poi.slug.eq_ignore_ascii_case(name)
+                })
+            });
+        let callsign = poi.map(|poi| poi.call).unwrap_or(&callsign);
+        let label = match poi {
+            Some(poi) => format!("{} ({})", poi.call, poi.name),
+            None => callsign.to_string(),
+        };
+        match self.aprs_cache.last_position(callsign) {
+            Some((ts, report)) => format!(
+                "{} at {}, {} ago",
+                label,
+                self.brc.rgeocode(report.pos.location),
+                format_age(*ts, now)
+            ),
+            None => format!("{} not heard lately", label),
+        }
+    }
+
+    fn answer_near(&self, address: &str, now: Timestamp) -> String {
+        let center = match self.parse_address(address) {
+            Some(pt) => pt,
+            None => return format!("Bad address {:?}, try e.g. NEAR 4:30&C", address),
+        };
+        let mut near = self
+            .aprs_cache
+            .last_positions()
+            .filter(|(ts, _)| *ts <= now && ts.duration_between(now) <= NEAR_MAX_AGE)
+            .map(|(_, report)| {
+                let dist_m = report.pos.location.haversine_distance_m(center);
+                (dist_m, report.src_callsign.as_str())
+            })
+            .filter(|(dist_m, _)| *dist_m <= NEAR_RADIUS_M)
+            .collect::<Vec<_>>();
+        if near.is_empty() {
+            return format!("Nobody near {}", address);
+        }
+        near.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut res = format!("Near {}:", address);
+        for (dist_m, callsign) in near {
+            let item = format!(" {} {:.0}ft", callsign, crate::util::units::m2ft(dist_m));
+            if res.len() + item.len() > aprs::Outbox::MAX_TEXT_LEN {
+                break;
+            }
+            res.push_str(&item);
+        }
+        res
+    }
+
+    fn answer_wx(&self, now: Timestamp) -> String {
+        let latest = self
+            .aprs_cache
+            .last_weather()
+            .filter(|(ts, _)| *ts <= now)
+            .max_by_key(|(ts, _)| *ts);
+        let (ts, wx) = match latest {
+            Some(v) => v,
+            None => return "No weather reports lately".into(),
+        };
+        let mut res = format!("WX {} ago:", format_age(*ts, now));
+        if let Some(c) = wx.temperature_c {
+            res.push_str(&format!(" {:.0}F", c * 9. / 5. + 32.));
+        }
+        if let Some(pct) = wx.humidity_pct {
+            res.push_str(&format!(" {:.0}%", pct));
+        }
+        if let Some(mps) = wx.wind_speed_mps {
+            let dir = match wx.wind_dir_deg {
+                Some(deg) => format!(" from {:.0}", deg),
+                None => String::new(),
+            };
+            res.push_str(&format!(" wind {:.0}mph{}", mps * MPH_IN_MPS, dir));
+        }
+        if let Some(mps) = wx.wind_gust_mps {
+            res.push_str(&format!(" gust {:.0}mph", mps * MPH_IN_MPS));
+        }
+        if let Some(hpa) = wx.pressure_hpa {
+            res.push_str(&format!(" {:.0}hPa", hpa));
+        }
+        res
+    }
+
+    /// Parses `H[:MM]&STREET` or `H[:MM]&DISTANCE'` (feet from the Man)
+    fn parse_address(&self, address: &str) -> Option<crate::util::geo::Point> {
+        let (clock, street) = address.split_once('&')?;
+        let clock = clock.trim();
+        let cp = match clock.split_once(':') {
+            Some((h, m)) => ClockPos::new(h.parse().ok()?, m.parse().ok()?),
+            None => ClockPos::new(clock.parse().ok()?, 0),
+        }
+        .ok()?;
+        let street = street.trim();
+        let distance_m = if let Some(ft) = street.strip_suffix('\'') {
+            ft2m(ft.trim().parse().ok()?)
+        } else if street.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            self.brc.ring_by_name(street).ok()?.radius_m()
+        } else {
+            return None;
+        };
+        Some(self.brc.geocode(cp, distance_m))
+    }
+}
+
+/// Formats time since `ts` as e.g. `5m` or `2h10m`
+fn format_age(ts: Timestamp, now: Timestamp) -> String {
+    let mins = if ts < now {
+        ts.duration_between(now).as_secs() / 60
+    } else {
+        0
+    };
+    match mins {
+        0 => "<1m".into(),
+        1..=59 => format!("{}m", mins),
+        60..=1439 => format!("{}h{:02}m", mins / 60, mins % 60),
+        _ => format!("{}d", mins / 1440),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server() -> Server {
+        let (_, user_evt_rx) = mpsc::channel(1);
+        let (_, aprs_dta_rx) = mpsc::channel(1);
+        let store = JsonLog::<io::store::Record>::new("/dev/null");
+        Server::new(user_evt_rx, aprs_dta_rx, Box::new(store))
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Query::parse("where tgecko"),
+            Some(Query::Where("tgecko".into()))
+        );
+        assert_eq!(
+            Query::parse(" NEAR 4:30&C "),
+            Some(Query::Near("4:30&C".into()))
+        );
+        assert_eq!(Query::parse("wx"), Some(Query::Wx));
+        assert_eq!(Query::parse("?"), Some(Query::Help));
+        assert_eq!(Query::parse("WHERE"), None);
+        assert_eq!(Query::parse("hello there"), None);
+    }
+
+    #[test]
+    fn test_answer() {
+        let mut server = server();
+        let now = Timestamp::from_calendar_utc(2023, 8, 29, 17, 5, 0).unwrap();
+        let heard = Timestamp::from_calendar_utc(2023, 8, 29, 17, 0, 0).unwrap();
+        let pt = server.brc.geocode(ClockPos::new(4, 30).unwrap(), 800.);
+        let line = format!(
+            "TGECKO>APDR15:!{:02}{:05.2}N/{:03}{:05.2}W>",
+            pt.lat() as u32,
+            pt.lat().fract() * 60.,
+            -pt.lng() as u32,
+            -pt.lng().fract() * 60.,
+        );
+        server.aprs_cache.push(heard, line).unwrap();
+        server
+            .aprs_cache
+            .push(
+                heard,
+                "WXSTN>APRS:_08291700c270s005g010t090h12b10120".into(),
+            )
+            .unwrap();
+
+        let answer = server.answer_query(&Query::Where("techno gecko".into()), now);
+        assert!(
+            answer.starts_with("TGECKO (Techno Gecko) at 4:") && answer.ends_with(", 5m ago"),
+            "{}",
+            answer
+        );
+        assert_eq!(
+            server.answer_query(&Query::Where("duck".into()), now),
+            "DUCK (The Duck) not heard lately"
+        );
+
+        let answer = server.answer_query(&Query::Near("4:30 & 2625'".into()), now);
+        assert!(
+            answer.starts_with("Near 4:30 & 2625': TGECKO "),
+            "{}",
+            answer
+        );
+        assert_eq!(
+            server.answer_query(&Query::Near("8&K".into()), now),
+            "Nobody near 8&K"
+        );
+        assert!(server
+            .answer_query(&Query::Near("25&C".into()), now)
+            .starts_with("Bad address"));
+
+        assert_eq!(
+            server.answer_query(&Query::Wx, now),
+            "WX 5m ago: 90F 12% wind 5mph from 270 gust 10mph 1012hPa"
+        );
+    }
+}
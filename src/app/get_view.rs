@@ -3,16 +3,29 @@ use crate::{
     aprs,
     brc::BlackRockCity,
     io,
-    util::{geo::Point, time::Timestamp},
+    util::{
+        geo::{LineString, Point},
+        time::{Duration, Timestamp},
+    },
 };
 use serde_json::json;
 
+/// How far back the trail of the station being looked at can go; that one
+/// comes from the event store, which may well have the whole event
+const FEATURE_TRAIL_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 3600);
+
 #[derive(Debug, Clone)]
 struct Poi {
+    callsign: String,
     name: String,
     location: Point,
     location_str: String,
     heading_deg: Option<f64>,
+    altitude_m: Option<f64>,
+    symbol: Option<aprs::Symbol>,
+    tocall: Option<String>,
+    source: Option<String>,
+    digipeater: Option<String>,
     lastseen: Timestamp,
     near_brc: bool,
     seen_recently: bool,
@@ -22,13 +35,25 @@ struct Poi {
 }
 
 impl Server {
-    pub async fn view(
-        &self, /* , _feature: Option<String>, _bbox: Option<BBox>, _zoom: Option<f64> */
-    ) -> Result<io::user::View> {
+    pub async fn view(&mut self, query: &io::user::Query) -> Result<io::user::View> {
+        let speed = query.speed.unwrap_or(1.);
+        if !(speed.is_finite() && speed > 0.) {
+            return Err(Error::BadRequest(format!("bad playback speed {}", speed)));
+        }
+        let (now, past) = match query.time {
+            Some(ts) if ts < Timestamp::now() => {
+                self.advance_playback(ts).await?;
+                (ts, true)
+            }
+            _ => (Timestamp::now(), false),
+        };
+
         let show_default_world = false;
         let city = &self.brc;
-        let log = &self.aprs_cache;
-        let now = Timestamp::now();
+        let log = match &self.playback {
+            Some((_, log)) if past => log,
+            _ => &self.aprs_cache,
+        };
         let mut features: Vec<geojson::Feature> = vec![];
 
         let mut pois = log
@@ -45,6 +70,7 @@ impl Server {
                 let lastseen = *ts;
                 let mut known = false;
                 let mut favorite = false;
+                let heard = log.last_heard(&pr.src_callsign);
 
                 if let Some(poi) = self.pois_by_call.get(pr.src_callsign.as_str()) {
                     known = true;
@@ -54,6 +80,7 @@ impl Server {
                 }
 
                 Poi {
+                    callsign: pr.src_callsign.clone(),
                     name,
                     slug,
                     near_brc,
@@ -64,6 +91,11 @@ impl Server {
                     known,
                     favorite,
                     heading_deg,
+                    altitude_m: pr.altitude_m,
+                    symbol: pr.symbol,
+                    tocall: pr.dst_callsign.clone(),
+                    source: heard.and_then(|h| h.source.clone()),
+                    digipeater: heard.and_then(|h| h.digipeater().map(|v| v.to_string())),
                 }
             })
             .filter(|poi| show_default_world || poi.near_brc)
@@ -79,6 +111,50 @@ impl Server {
             )
         });
 
+        if let Some(mins) = query.trail {
+            let full_window = Duration::from_secs(mins as u64 * 60).min(FEATURE_TRAIL_MAX_AGE);
+            let window = full_window.min(aprs::TRACK_MAX_AGE);
+            for poi in pois.iter() {
+                let points = if query.feature.as_ref() == Some(&poi.slug) {
+                    // Whole history of the station being looked at
+                    let span = Timespan::from_two_timestamps(
+                        now.saturating_sub(full_window),
+                        now.saturating_add(Duration::from_millis(1)),
+                    );
+                    self.track_from_store(&poi.callsign, span).await?
+                } else {
+                    log.track(&poi.callsign)
+                        .filter(|(ts, _)| ts.duration_between(now) <= window || *ts > now)
+                        .map(|(_, pt)| *pt)
+                        .collect::<Vec<_>>()
+                };
+                if points.len() < 2 {
+                    continue;
+                }
+                features.push(geojson::Feature {
+                    geometry: Some(geojson::Geometry {
+                        bbox: None,
+                        value: LineString::new(points).into(),
+                        foreign_members: None,
+                    }),
+                    bbox: None,
+                    id: None,
+                    foreign_members: None,
+                    properties: Some(
+                        serde_json::json!({
+                            "liveplaya": "trail",
+                            "name": poi.name,
+                            "slug": poi.slug,
+                            "_fav": poi.favorite,
+                        })
+                        .as_object()
+                        .unwrap()
+                        .clone(),
+                    ),
+                });
+            }
+        }
+
         let mut priority = 0;
         for poi in pois.iter() {
             priority += 1;
@@ -97,6 +173,12 @@ impl Server {
                         "poi": "beacon",
                         "name": poi.name,
                         "headingDeg": poi.heading_deg,
+                        "altitudeM": poi.altitude_m,
+                        "symbol": poi.symbol,
+                        "icon": poi.symbol.map(|s| format!("aprs-{}", s.id())),
+                        "tocall": poi.tocall,
+                        "source": poi.source,
+                        "digipeater": poi.digipeater,
                         "location": poi.location_str,
                         "lastseen": poi.lastseen,
                         "priority": priority,
@@ -109,6 +191,40 @@ impl Server {
                 ),
             });
         }
+
+        let mut objects = log
+            .objects()
+            .filter(|(_ts, obj)| {
+                show_default_world
+                    || obj.pos.location.haversine_distance_m(city.center())
+                        < BlackRockCity::DEFAULT_WORLD_THRESHOLD_M
+            })
+            .collect::<Vec<_>>();
+        objects.sort_by(|a, b| a.1.name.cmp(&b.1.name));
+
+        for (ts, obj) in objects.iter() {
+            features.push(geojson::Feature {
+                geometry: Some(geojson::Geometry {
+                    bbox: None,
+                    value: obj.pos.location.into(),
+                    foreign_members: None,
+                }),
+                bbox: None,
+                id: None,
+                foreign_members: None,
+                properties: Some(as_map(json!({
+                    "liveplaya": "poi",
+                    "poi": "object",
+                    "name": obj.name,
+                    "symbol": obj.symbol,
+                    "icon": obj.symbol.map(|s| format!("aprs-{}", s.id())),
+                    "location": city.rgeocode(obj.pos.location),
+                    "lastseen": ts,
+                    "reporter": obj.src_callsign,
+                }))),
+            });
+        }
+
         for f in city.other_features() {
             features.push(f.clone());
         }
@@ -224,20 +340,26 @@ impl Server {
         };
 
         let mut logmsgs = Vec::new();
-        for (id, ts, raw, parsed) in log.recent_entries() {
-            match parsed {
-                Ok(aprs::Packet::Position(_pr)) => {
+        for entry in log.recent_entries() {
+            match &entry.packet {
+                Ok(_) => {
                     logmsgs.push(io::user::LogMessage::Info {
-                        id: *id,
-                        time: *ts,
-                        text: format!("{}", raw),
+                        id: entry.id,
+                        time: entry.ts,
+                        text: entry.data.clone(),
+                        source: entry.heard[0].source.clone(),
+                        dups: entry.dups,
+                        paths: entry.heard.iter().map(|h| h.to_string()).collect(),
                     });
                 }
                 Err(e) => {
                     logmsgs.push(io::user::LogMessage::Error {
-                        id: *id,
-                        time: *ts,
-                        text: format!("{}: {}", raw, e),
+                        id: entry.id,
+                        time: entry.ts,
+                        text: format!("{}: {}", entry.data, e),
+                        source: entry.heard[0].source.clone(),
+                        dups: entry.dups,
+                        paths: entry.heard.iter().map(|h| h.to_string()).collect(),
                     });
                 }
             }
@@ -250,13 +372,78 @@ impl Server {
                 slug: poi.slug.to_string(),
                 location: poi.location_str.to_string(),
                 lastseen: poi.lastseen,
+                source: poi.source.clone(),
+                digipeater: poi.digipeater.clone(),
             });
         }
 
+        let mut weather = log
+            .last_weather()
+            .filter_map(|(ts, wx)| {
+                let location = wx.pos.as_ref().map(|pos| pos.location).or_else(|| {
+                    log.last_position(&wx.src_callsign)
+                        .map(|(_, pr)| pr.pos.location)
+                });
+                let poi = self.pois_by_call.get(wx.src_callsign.as_str());
+                let near_brc = location
+                    .map(|pt| {
+                        pt.haversine_distance_m(city.center())
+                            < BlackRockCity::DEFAULT_WORLD_THRESHOLD_M
+                    })
+                    .unwrap_or(false);
+                if !(show_default_world || near_brc || poi.is_some()) {
+                    return None;
+                }
+                Some(io::user::Weather {
+                    name: poi
+                        .map(|p| p.name.to_string())
+                        .unwrap_or(wx.src_callsign.clone()),
+                    slug: poi
+                        .map(|p| p.slug.to_string())
+                        .unwrap_or(format!("aprs/{}", wx.src_callsign.to_ascii_lowercase())),
+                    location: location.map(|pt| city.rgeocode(pt)),
+                    time: *ts,
+                    wind_dir_deg: wx.wind_dir_deg,
+                    wind_speed_mps: wx.wind_speed_mps,
+                    wind_gust_mps: wx.wind_gust_mps,
+                    temperature_c: wx.temperature_c,
+                    humidity_pct: wx.humidity_pct,
+                    pressure_hpa: wx.pressure_hpa,
+                })
+            })
+            .collect::<Vec<io::user::Weather>>();
+        weather.sort_by_key(|wx| std::cmp::Reverse(wx.time));
+
+        for (ts, obj) in objects.iter() {
+            refs.push(io::user::FeatureRef::Object {
+                name: obj.name.clone(),
+                slug: obj.slug(),
+                location: city.rgeocode(obj.pos.location),
+                lastseen: *ts,
+                reporter: obj.src_callsign.clone(),
+            });
+        }
+
+        let mut bulletins = log
+            .bulletins()
+            .map(|(ts, msg)| io::user::Bulletin {
+                id: msg.addressee.clone(),
+                from: self
+                    .pois_by_call
+                    .get(msg.src_callsign.as_str())
+                    .map(|poi| poi.name.to_string())
+                    .unwrap_or(msg.src_callsign.clone()),
+                text: msg.text.clone(),
+                time: *ts,
+            })
+            .collect::<Vec<io::user::Bulletin>>();
+        bulletins.sort_by(|a, b| a.id.cmp(&b.id).then(b.time.cmp(&a.time)));
+
         let view = io::user::View {
             name: format!("Black Rock City {}", city.year()),
             description: Some(format!("Watching {} APRS stations.", log.station_count())),
-            time: Timestamp::now(),
+            time: now,
+            speed: past.then_some(speed),
             map: Some(io::user::Map {
                 bearing_deg: 45.,
                 center: city.center().lnglat(),
@@ -265,10 +452,114 @@ impl Server {
             }),
             log: logmsgs,
             refs,
+            weather,
+            bulletins,
+            // What we were sending back then is long gone
+            outbox: match past {
+                true => vec![],
+                false => self
+                    .outbox
+                    .entries()
+                    .rev()
+                    .map(send_message::outgoing_message)
+                    .collect(),
+            },
         };
 
         Ok(view)
     }
+
+    /// Positions the station reported within the span, from the event store
+    /// rather than the in-memory track, so that they can go further back.
+    /// Ordered by when they were sent like the in-memory ones, and no more
+    /// of them than those either.
+    async fn track_from_store(&self, callsign: &str, span: Timespan) -> Result<Vec<Point>> {
+        let mut records = self.store.records_from(callsign, span).await?;
+        let mut track = vec![];
+        while let Some((ts, rec)) = records.next().await? {
+            let data = match rec {
+                io::store::Record::AprsPacket { data, .. } => data,
+            };
+            let packet = match aprs::Packet::parse(&data) {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+            if let aprs::Packet::Position(report) = &packet {
+                track.push((packet.time(ts), report.pos.location));
+            }
+            // Records come in receive order, which is close enough to send
+            // order to drop the oldest early
+            if track.len() > 2 * aprs::TRACK_MAX_POINTS {
+                track.sort_by_key(|(ts, _)| *ts);
+                track.drain(..track.len() - aprs::TRACK_MAX_POINTS);
+            }
+        }
+        track.sort_by_key(|(ts, _)| *ts);
+        let mut res = vec![];
+        for (_, location) in track {
+            // Parked stations keep beaconing the same spot, that's not a trail
+            if res.last() != Some(&location) {
+                res.push(location);
+            }
+        }
+        if res.len() > aprs::TRACK_MAX_POINTS {
+            res.drain(..res.len() - aprs::TRACK_MAX_POINTS);
+        }
+        Ok(res)
+    }
+
+    /// Brings the playback log to `ts`: moves the last one along if `ts` is
+    /// a little later, as it is while a client plays, otherwise rebuilds it
+    async fn advance_playback(&mut self, ts: Timestamp) -> Result<()> {
+        let (from, mut log) = match self.playback.take() {
+            Some((prev, log)) if prev <= ts && ts.duration_between(prev) < aprs::TRACK_MAX_AGE => {
+                (prev, log)
+            }
+            _ => {
+                let log = self.log_at(ts).await?;
+                self.playback = Some((ts, log));
+                return Ok(());
+            }
+        };
+        // Spans don't include their end, and `from` itself is in already
+        let span = Timespan::from_two_timestamps(
+            from.saturating_add(Duration::from_millis(1)),
+            ts.saturating_add(Duration::from_millis(1)),
+        );
+        self.push_records(&mut log, span).await?;
+        log.expire(ts);
+        self.playback = Some((ts, log));
+        Ok(())
+    }
+
+    /// Rebuilds APRS log as of `ts` from the event store, going back as far
+    /// as the longest trail
+    async fn log_at(&self, ts: Timestamp) -> Result<aprs::Log> {
+        // Spans don't include their end
+        let span = Timespan::from_two_timestamps(
+            ts.saturating_sub(aprs::TRACK_MAX_AGE),
+            ts.saturating_add(Duration::from_millis(1)),
+        );
+        let mut log = aprs::Log::new();
+        if let Some(callsign) = &self.callsign {
+            log.set_own_callsign(callsign.clone());
+        }
+        self.push_records(&mut log, span).await?;
+        log.expire(ts);
+        Ok(log)
+    }
+
+    async fn push_records(&self, log: &mut aprs::Log, span: Timespan) -> Result<()> {
+        let mut records = self.store.records(span).await?;
+        while let Some((ts, rec)) = records.next().await? {
+            match rec {
+                io::store::Record::AprsPacket { data, source, .. } => {
+                    log.push_from(ts, source, data).log_result();
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 fn as_map(v: serde_json::Value) -> serde_json::Map<String, serde_json::Value> {
@@ -277,3 +568,136 @@ fn as_map(v: serde_json::Value) -> serde_json::Map<String, serde_json::Value> {
         _ => panic!("expected object value"),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_playback() {
+        let path = std::env::temp_dir().join(format!("lpkiosk-test-{}.log", std::process::id()));
+        let mut store = JsonLog::new(&path);
+        let t0 = Timestamp::from_calendar_utc(2023, 8, 30, 21, 0, 0).unwrap();
+        let t1 = t0.add(Duration::from_secs(600)).unwrap();
+        for (ts, data) in [
+            (t0, "TGECKO>APDR15:!4046.40N/11912.12W>"),
+            (t1, "TGECKO>APDR15:!4047.40N/11912.12W>"),
+        ] {
+            let rec = io::store::Record::AprsPacket {
+                data: data.into(),
+                source: None,
+                path: vec![],
+                digipeater: None,
+            };
+            store.write(ts, &rec).await.unwrap();
+        }
+        let (_, user_evt_rx) = mpsc::channel(1);
+        let (_, aprs_dta_rx) = mpsc::channel(1);
+        let mut server = Server::new(user_evt_rx, aprs_dta_rx, Box::new(store));
+
+        let query = |time: Timestamp| io::user::Query {
+            feature: None,
+            bounds: None,
+            zoom: None,
+            trail: Some(60),
+            time: Some(time),
+            speed: Some(60.),
+        };
+        let lastseen = |view: &io::user::View| match &view.refs[..] {
+            [io::user::FeatureRef::Beacon { lastseen, .. }] => *lastseen,
+            refs => panic!("expected one beacon, got {:?}", refs),
+        };
+        let view = server.view(&query(t0)).await.unwrap();
+        assert_eq!(lastseen(&view), t0);
+        assert_eq!(view.time, t0);
+        assert_eq!(view.speed, Some(60.));
+
+        // Log is moved along, and now there's a trail too
+        let view = server.view(&query(t1)).await.unwrap();
+        assert_eq!(lastseen(&view), t1);
+        let trails = view
+            .map
+            .unwrap()
+            .data
+            .features
+            .iter()
+            .filter(|f| f.property("liveplaya") == Some(&json!("trail")))
+            .count();
+        assert_eq!(trails, 1);
+
+        // Going back rebuilds it
+        let view = server.view(&query(t0)).await.unwrap();
+        assert_eq!(lastseen(&view), t0);
+
+        // Live view doesn't know about the past
+        let mut live = query(t1);
+        live.time = None;
+        assert!(server.view(&live).await.unwrap().refs.is_empty());
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_feature_trail() {
+        use crate::io::store::Store;
+        let path = std::env::temp_dir().join(format!("lpkiosk-trail-{}.db", std::process::id()));
+        std::fs::remove_file(&path).ok();
+        let mut store = crate::svc::sqlite::SqliteStore::open(&path, Fsync::Never).unwrap();
+        let t0 = Timestamp::from_calendar_utc(2023, 8, 28, 21, 0, 0).unwrap();
+        let at = |hours: u64| t0.add(Duration::from_secs(hours * 3600)).unwrap();
+        for (ts, data) in [
+            (at(0), "TGECKO>APDR15:!4046.40N/11912.12W>"),
+            (at(25), "TGECKO>APDR15:!4047.40N/11912.12W>"),
+            // Stored and forwarded, sent half an hour before the one above
+            (at(25), "TGECKO>APDR15:/292130z4049.40N/11912.12W>"),
+            (at(26), "TGECKO>APDR15:!4048.40N/11912.12W>"),
+        ] {
+            let rec = io::store::Record::AprsPacket {
+                data: data.into(),
+                source: None,
+                path: vec![],
+                digipeater: None,
+            };
+            store.append(ts, &rec).await.unwrap();
+        }
+        let (_, user_evt_rx) = mpsc::channel(1);
+        let (_, aprs_dta_rx) = mpsc::channel(1);
+        let mut server = Server::new(user_evt_rx, aprs_dta_rx, Box::new(store));
+
+        let mut query = io::user::Query {
+            feature: None,
+            bounds: None,
+            zoom: None,
+            trail: Some(3 * 24 * 60),
+            time: Some(at(26)),
+            speed: None,
+        };
+        let trail = |view: io::user::View| {
+            let trail = view
+                .map
+                .unwrap()
+                .data
+                .features
+                .into_iter()
+                .find(|f| f.property("liveplaya") == Some(&json!("trail")))
+                .unwrap();
+            match trail.geometry.unwrap().value {
+                geojson::Value::LineString(points) => points,
+                v => panic!("expected line, got {:?}", v),
+            }
+        };
+        let lat = |points: &Vec<Vec<f64>>| points.iter().map(|v| v[1]).collect::<Vec<_>>();
+        let expected = [40.7733, 40.8233, 40.7900, 40.8067];
+
+        // Others' trails only go as far back as tracks are kept
+        let points = trail(server.view(&query).await.unwrap());
+        assert_eq!(points.len(), 3);
+        query.feature = Some("tgecko".into());
+        let points = trail(server.view(&query).await.unwrap());
+        // In the order they were sent, not received
+        assert_eq!(points.len(), 4);
+        for (lat, expected) in lat(&points).into_iter().zip(expected) {
+            assert!((lat - expected).abs() < 0.001, "{} != {}", lat, expected);
+        }
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -2,7 +2,13 @@ use super::*;
 use crate::util::time::Timestamp;
 
 impl Server {
-    pub async fn post_aprs(&mut self, ts: Timestamp, data: String) -> Result<()> {
-        self.aprs_cache.push(ts, data.trim().to_string())
+    pub async fn post_aprs(
+        &mut self,
+        ts: Timestamp,
+        source: Option<String>,
+        data: String,
+    ) -> Result<()> {
+        self.aprs_cache
+            .push_from(ts, source, data.trim().to_string())
     }
 }
@@ -0,0 +1,22 @@
+use super::*;
+
+impl Server {
+    /// Transmits our position and objects, and records them as if we've
+    /// heard them ourselves
+    pub async fn send_beacon(&mut self) -> Result<()> {
+        let (beacon, tnc_tx) = match (&self.beacon, &self.tnc_tx) {
+            (Some(beacon), Some(tnc_tx)) => (beacon, tnc_tx),
+            _ => return Ok(()),
+        };
+        let mut packets = vec![beacon.position_packet()];
+        packets.extend(beacon.object_packets(Timestamp::now()));
+        for packet in packets.iter() {
+            tnc_tx.try_send(packet.clone())?;
+        }
+        for packet in packets {
+            let rx = io::aprs::Received::new(OWN_SOURCE, packet);
+            self.record_aprs_data(rx).await.log_result();
+        }
+        Ok(())
+    }
+}
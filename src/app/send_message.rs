@@ -0,0 +1,170 @@
+use super::*;
+use crate::aprs;
+
+/// How long we remember messages we've acked, senders give up retrying
+/// well before that
+const ANSWERED_WINDOW: std::time::Duration = std::time::Duration::from_secs(30 * 60);
+
+impl Server {
+    /// Queues message for transmission, see `send_outbox`
+    pub async fn queue_message(
+        &mut self,
+        req: io::user::NewMessage,
+    ) -> Result<io::user::OutgoingMessage> {
+        let callsign = match (&self.callsign, &self.tnc_tx) {
+            (Some(callsign), Some(_)) => callsign,
+            _ => return Err(Error::BadRequest("this kiosk can't send messages".into())),
+        };
+        let entry = self
+            .outbox
+            .queue(callsign, &req.to, &req.text, Timestamp::now())?;
+        let res = outgoing_message(entry);
+        self.send_outbox().await?;
+        Ok(res)
+    }
+
+    /// Transmits messages that are due for (re)transmission
+    pub async fn send_outbox(&mut self) -> Result<()> {
+        // Attempts are counted already, so one failing mustn't hold up the
+        // rest; they'll be retried as usual
+        for msg in self.outbox.due(Timestamp::now()) {
+            self.transmit(msg.to_info()).await.log_result();
+        }
+        Ok(())
+    }
+
+    /// Handles acks for our messages, acks messages sent to us and answers
+    /// the ones that are queries; `rf` is whether we heard it over the air,
+    /// we only ack and answer those since the sender can't hear us otherwise
+    pub async fn process_message(&mut self, msg: aprs::Message, rf: bool) -> Result<()> {
+        let callsign = match &self.callsign {
+            Some(v) if msg.addressee.eq_ignore_ascii_case(v) => v.clone(),
+            _ => return Ok(()),
+        };
+        if let Some((accepted, msgno)) = msg.reply() {
+            if !self.outbox.handle_reply(&msg.src_callsign, msgno, accepted) {
+                log::debug!("unexpected reply {:?}", msg);
+            }
+            return Ok(());
+        }
+        let now = Timestamp::now();
+        // Sender's retries and copies through digipeaters or APRS-IS
+        let key = (
+            msg.src_callsign.to_ascii_uppercase(),
+            msg.msgno.clone().unwrap_or_else(|| msg.text.clone()),
+        );
+        let is_dupe = self.answered.insert(key, now).is_some();
+        if rf {
+            if let Some(ack) = msg.ack(&callsign) {
+                self.transmit(ack.to_info()).await?;
+            }
+        }
+        if is_dupe {
+            log::debug!("duplicate message from {}", msg.src_callsign);
+            return Ok(());
+        }
+        log::info!("message from {}: {}", msg.src_callsign, msg.text);
+
+        let query = match answer_query::Query::parse(&msg.text) {
+            Some(v) if self.answer_queries && rf => v,
+            Some(_) if self.answer_queries => {
+                // Answer would only be retried until it gives up
+                log::debug!("not answering {}, not heard on RF", msg.src_callsign);
+                return Ok(());
+            }
+            _ => return Ok(()),
+        };
+        let answer = self.answer_query(&query, now);
+        log::info!("answering {}: {}", msg.src_callsign, answer);
+        self.outbox
+            .queue(&callsign, &msg.src_callsign, &answer, now)?;
+        self.send_outbox().await
+    }
+
+    /// Forgets messages old enough not to be retried anymore
+    pub fn expire_answered(&mut self, now: Timestamp) {
+        self.answered
+            .retain(|_, ts| ts.duration_between(now) < ANSWERED_WINDOW);
+    }
+
+    /// Sends packet with given info field through the TNC and records it
+    async fn transmit(&mut self, info: String) -> Result<()> {
+        let (callsign, tnc_tx) = match (&self.callsign, &self.tnc_tx) {
+            (Some(callsign), Some(tnc_tx)) => (callsign, tnc_tx),
+            _ => return Err(Error::Other("no TNC to transmit with".into())),
+        };
+        let path = match &self.beacon {
+            Some(beacon) => beacon.path.as_str(),
+            None => crate::beacon::DEFAULT_PATH,
+        };
+        let packet = crate::beacon::packet(callsign, path, &info);
+        tnc_tx.try_send(packet.clone())?;
+        self.record_aprs_data(io::aprs::Received::new(OWN_SOURCE, packet))
+            .await
+    }
+}
+
+pub fn outgoing_message(entry: &aprs::OutboxEntry) -> io::user::OutgoingMessage {
+    io::user::OutgoingMessage {
+        id: entry.id,
+        to: entry.msg.addressee.clone(),
+        text: entry.msg.text.clone(),
+        time: entry.queued,
+        state: entry.state,
+        attempts: entry.attempts,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_process_message() {
+        let (_, user_evt_rx) = mpsc::channel(1);
+        let (_, aprs_dta_rx) = mpsc::channel(1);
+        let (tnc_tx, mut tnc_rx) = mpsc::channel(16);
+        let store = JsonLog::<io::store::Record>::new("/dev/null");
+        let mut server = Server::new(user_evt_rx, aprs_dta_rx, Box::new(store))
+            .with_transmitter("KIOSK".into(), tnc_tx)
+            .with_query_answers();
+        let mut sent = || {
+            let mut res = vec![];
+            while let Ok(packet) = tnc_rx.try_recv() {
+                res.push(packet.split_once(':').unwrap().1.to_string());
+            }
+            res
+        };
+        let msg = |body: &str| aprs::Message::parse("TGECKO", body).unwrap();
+
+        server
+            .process_message(msg(":KIOSK    :wx{12"), true)
+            .await
+            .unwrap();
+        let res = sent();
+        assert_eq!(res.len(), 2, "{:?}", res);
+        assert_eq!(res[0], ":TGECKO   :ack12");
+        assert!(res[1].starts_with(":TGECKO   :"), "{:?}", res);
+
+        // Retry gets acked again, but not answered
+        server
+            .process_message(msg(":KIOSK    :wx{12"), true)
+            .await
+            .unwrap();
+        assert_eq!(sent(), vec![":TGECKO   :ack12"]);
+
+        // Heard on APRS-IS only, so nothing goes over the air
+        server
+            .process_message(msg(":KIOSK    :wx{13"), false)
+            .await
+            .unwrap();
+        assert!(sent().is_empty());
+        server.send_outbox().await.unwrap();
+        assert_eq!(server.outbox.entries().count(), 1);
+
+        // Replayed ones are only shown
+        let rx = io::aprs::Received::new("replay", "TGECKO>APRS::KIOSK    :wx{14");
+        server.process_demo_data(rx).await.unwrap();
+        assert!(sent().is_empty());
+    }
+}
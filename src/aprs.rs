@@ -3,7 +3,7 @@ use crate::{
     motion::Position,
     util::{
         geo::Point,
-        time::Timestamp,
+        time::{Duration, Timestamp},
         units::ft2m,
     },
 };
@@ -11,10 +11,43 @@ use ::aprs::Packet as PacketTrait;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, VecDeque};
 
+mod message;
+mod object;
+mod outbox;
+mod symbol;
+mod timestamp;
+mod weather;
+
+pub use message::Message;
+pub use object::{ObjectKind, ObjectReport};
+pub use outbox::{DeliveryState, Outbox, OutboxEntry};
+pub use symbol::Symbol;
+pub use timestamp::PacketTime;
+pub use weather::WeatherReport;
+
+/// How long bulletins stay on the board unless they are re-sent
+pub const BULLETIN_TTL: Duration = Duration::from_secs(6 * 3600);
+
+/// Packets with the same source, destination and payload heard within this
+/// long are copies of the same packet, e.g. via different digipeaters or
+/// both RF and APRS-IS; for our own packets, echoes of what we've sent
+pub const DUPE_WINDOW: Duration = Duration::from_secs(30);
+
+/// How far back station tracks go, i.e. the longest trail we can show
+pub const TRACK_MAX_AGE: Duration = Duration::from_secs(24 * 3600);
+
+/// Most points kept per station track, so a chatty tracker can't eat all
+/// the memory
+pub const TRACK_MAX_POINTS: usize = 2000;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum Packet {
     Position(PositionReport),
+    Weather(WeatherReport),
+    Message(Message),
+    Bulletin(Message),
+    Object(ObjectReport),
 }
 impl Packet {
     pub fn parse(data: impl AsRef<str>) -> Result<Self> {
@@ -25,12 +58,34 @@ impl Packet {
         })?;
 
         let srccall = res.source();
+        let body = res.body();
+        let timestamp = PacketTime::parse(&body);
+        if body.starts_with('_') {
+            return WeatherReport::parse_positionless(&srccall, &body)
+                .map(|wx| Self::Weather(WeatherReport { timestamp, ..wx }))
+                .ok_or_else(|| Error::AprsParse {
+                    what: data.to_string(),
+                    why: "bad weather report".into(),
+                });
+        }
+
+        if body.starts_with(':') {
+            return match Message::parse(&srccall, &body) {
+                Some(msg) if msg.is_bulletin() => Ok(Self::Bulletin(msg)),
+                Some(msg) => Ok(Self::Message(msg)),
+                None => Err(Error::AprsParse {
+                    what: data.to_string(),
+                    why: "bad message".into(),
+                }),
+            };
+        }
+
         let pos = res.position();
         let speed = res.speed();
         let course = res.course();
-        let _symbol = res.symbol();
-        let _dstcsll = res.destination();
-        let _altitude = res.altitude();
+        let symbol = Symbol::parse(&body);
+        let dstcall = res.destination();
+        let altitude = res.altitude();
         let comment = res.comment();
 
         let pos = match pos {
@@ -48,20 +103,79 @@ impl Packet {
             }),
         }?;
 
+        if let Some((kind, name, alive)) = ObjectReport::parse_name(&body) {
+            return Ok(Self::Object(ObjectReport {
+                src_callsign: srccall.into(),
+                name,
+                kind,
+                alive,
+                pos,
+                symbol,
+                comment: comment.map(|v| v.into()),
+                timestamp,
+            }));
+        }
+
+        if symbol.map(|s| s.code == '_').unwrap_or(false) {
+            if let Some(wx) = WeatherReport::parse_with_position(&srccall, &body, pos.clone()) {
+                return Ok(Self::Weather(WeatherReport { timestamp, ..wx }));
+            }
+        }
+
         Ok(Self::Position(PositionReport {
             src_callsign: srccall.into(),
+            dst_callsign: dstcall.map(|v| v.into()),
             pos,
-            // symbol,
+            symbol,
+            altitude_m: altitude.map(|v| ft2m(v.as_f64())),
             comment: comment.map(|v| v.into()),
+            timestamp,
         }))
     }
 
+    /// Timestamp the sender put in the packet, if any
+    pub fn timestamp(&self) -> Option<PacketTime> {
+        match self {
+            Packet::Position(report) => report.timestamp,
+            Packet::Weather(report) => report.timestamp,
+            Packet::Object(report) => report.timestamp,
+            Packet::Message(_) | Packet::Bulletin(_) => None,
+        }
+    }
+
+    /// Returns when the packet was sent if it says so believably, otherwise
+    /// when it was received; packets delayed by digipeaters or stored and
+    /// forwarded are older than they look
+    pub fn time(&self, rx_ts: Timestamp) -> Timestamp {
+        match self.timestamp().and_then(|v| v.resolve(rx_ts)) {
+            // Sender's clock being a bit ahead doesn't make it news
+            Some(ts) => ts.min(rx_ts),
+            None => rx_ts,
+        }
+    }
+
     pub fn srccall(&self) -> &str {
         match self {
             Packet::Position(PositionReport {
                 src_callsign: srccall,
                 ..
             }) => srccall.as_str(),
+            Packet::Weather(WeatherReport {
+                src_callsign: srccall,
+                ..
+            }) => srccall.as_str(),
+            Packet::Message(Message {
+                src_callsign: srccall,
+                ..
+            })
+            | Packet::Bulletin(Message {
+                src_callsign: srccall,
+                ..
+            }) => srccall.as_str(),
+            Packet::Object(ObjectReport {
+                src_callsign: srccall,
+                ..
+            }) => srccall.as_str(),
         }
     }
 }
@@ -70,9 +184,98 @@ impl Packet {
 #[serde(rename_all = "camelCase")]
 pub struct PositionReport {
     pub src_callsign: String,
+    pub dst_callsign: Option<String>,
     pub pos: Position,
-    // symbol: (u8,u8),
+    pub symbol: Option<Symbol>,
+    pub altitude_m: Option<f64>,
     pub comment: Option<String>,
+    pub timestamp: Option<PacketTime>,
+}
+
+/// Where and how a packet reached us
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Heard {
+    /// Input it came from, unknown for packets stored before we kept track
+    pub source: Option<String>,
+    /// Digipeaters and q-construct, as in the packet's header
+    pub path: Vec<String>,
+}
+
+impl Heard {
+    pub fn new(source: Option<String>, data: &str) -> Self {
+        Self {
+            source,
+            path: parse_path(data),
+        }
+    }
+
+    /// Whether the packet came to us over the air rather than through
+    /// APRS-IS, which adds q-construct to the path
+    pub fn is_rf(&self) -> bool {
+        !self
+            .path
+            .iter()
+            .any(|v| v.starts_with("qA") || v.starts_with("TCPIP"))
+    }
+
+    /// Digipeater we heard the packet from, `None` if heard directly or
+    /// through digipeaters that don't leave their callsign in the path
+    pub fn digipeater(&self) -> Option<&str> {
+        // Anything after q-construct is APRS-IS servers and IGates
+        let rf_path = self
+            .path
+            .iter()
+            .take_while(|v| !v.starts_with("qA"))
+            .collect::<Vec<_>>();
+        let last_used = rf_path.iter().rposition(|v| v.ends_with('*'))?;
+        rf_path[..=last_used]
+            .iter()
+            .map(|&v| v.trim_end_matches('*'))
+            .rfind(|v| !is_path_alias(v))
+    }
+}
+
+impl std::fmt::Display for Heard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} via {}",
+            self.source.as_deref().unwrap_or("?"),
+            if self.path.is_empty() {
+                "direct".into()
+            } else {
+                self.path.join(",")
+            }
+        )
+    }
+}
+
+/// Returns path elements of TNC2 line, i.e. what's between the destination
+/// and the payload
+pub fn parse_path(data: &str) -> Vec<String> {
+    let header = data.split_once(':').map(|v| v.0).unwrap_or_default();
+    header.split(',').skip(1).map(|v| v.to_string()).collect()
+}
+
+/// Generic path elements, as opposed to digipeater callsigns
+fn is_path_alias(elem: &str) -> bool {
+    let name = elem.split('-').next().unwrap_or(elem);
+    name.starts_with("WIDE")
+        || name.starts_with("TRACE")
+        || ["RELAY", "TCPIP", "TCPXX", "NOGATE", "RFONLY"].contains(&name)
+}
+
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub id: u64,
+    pub ts: Timestamp,
+    /// Packet as first heard
+    pub data: String,
+    pub packet: Result<Packet>,
+    /// How the first copy and the duplicates after it reached us
+    pub heard: Vec<Heard>,
+    /// Number of duplicates heard
+    pub dups: u32,
 }
 
 #[derive(Debug, Clone)]
@@ -80,8 +283,34 @@ pub struct Log {
     /// A map between callsigns and most recent position updates
     lastpos: HashMap<String, (Timestamp, PositionReport)>,
 
+    /// A map between callsigns and most recent weather reports
+    lastwx: HashMap<String, (Timestamp, WeatherReport)>,
+
+    /// A map between object/item names and most recent reports about them,
+    /// including the ones that have been killed
+    objects: HashMap<String, (Timestamp, ObjectReport)>,
+
+    /// Active bulletins keyed by sender and bulletin id
+    bulletins: HashMap<(String, String), (Timestamp, Message)>,
+
+    /// Our callsign, if we transmit
+    own_callsign: Option<String>,
+
+    /// Our own packets (without path) and when we first saw them
+    own_sent: HashMap<String, Timestamp>,
+
+    /// Recent packets (without path) and when and as which entry we first
+    /// heard them
+    first_heard: HashMap<String, (Timestamp, u64)>,
+
+    /// A map between callsigns and how their most recent position reached us
+    lastheard: HashMap<String, Heard>,
+
+    /// A map between callsigns and where they've been lately, oldest first
+    tracks: HashMap<String, VecDeque<(Timestamp, Point)>>,
+
     /// Most recent packets entries
-    recent: VecDeque<(u64, Timestamp, String, Result<Packet>)>,
+    recent: VecDeque<LogEntry>,
 
     /// Max number of entries to store
     maxlen: usize,
@@ -95,6 +324,14 @@ impl Log {
         let maxlen = 32;
         Self {
             lastpos: HashMap::new(),
+            lastwx: HashMap::new(),
+            objects: HashMap::new(),
+            bulletins: HashMap::new(),
+            own_callsign: None,
+            own_sent: HashMap::new(),
+            first_heard: HashMap::new(),
+            lastheard: HashMap::new(),
+            tracks: HashMap::new(),
             recent: VecDeque::with_capacity(maxlen),
             maxlen,
             nextid: 1,
@@ -102,27 +339,88 @@ impl Log {
     }
 
     pub fn push(&mut self, ts: Timestamp, data: String) -> Result<()> {
-        if let Some((_msgid, last_ts, _last_data, _last_res)) = self.recent.back() {
-            if last_ts > &ts {
-                return Err(Error::AprsOrder {
-                    new_ts: ts.to_string(),
-                    last_ts: (*last_ts).to_string(),
-                });
-            }
+        self.push_from(ts, None, data)
+    }
+
+    /// Same as `push`, for packets whose input we know. Packets don't have
+    /// to come in chronological order, e.g. when merging several sources.
+    pub fn push_from(&mut self, ts: Timestamp, source: Option<String>, data: String) -> Result<()> {
+        if self.is_own_echo(ts, &data) {
+            log::debug!("ignoring echo of our own packet {:?}", data);
+            return Ok(());
         }
 
-        while self.recent.len() >= self.maxlen - 1 {
-            self.recent.pop_front();
+        let key = dupe_key(&data);
+        let heard = Heard::new(source, &data);
+        if let Some((first_ts, id)) = self.first_heard.get(&key) {
+            if first_ts.duration_between(ts) < DUPE_WINDOW {
+                let id = *id;
+                if let Some(entry) = self.recent.iter_mut().rev().find(|e| e.id == id) {
+                    entry.heard.push(heard);
+                    entry.dups += 1;
+                }
+                return Ok(());
+            }
         }
 
         let parsed = Packet::parse(data.clone());
-        if let Ok(Packet::Position(report)) = &parsed {
-            self.lastpos
-                .insert(report.src_callsign.clone(), (ts, report.clone()));
+        // Position and weather are as of when the packet was sent
+        let sent_ts = parsed.as_ref().map(|p| p.time(ts)).unwrap_or(ts);
+        match &parsed {
+            Ok(Packet::Position(report)) => {
+                let call = &report.src_callsign;
+                self.add_to_track(call, sent_ts, report.pos.location);
+                if insert_if_newer(&mut self.lastpos, call, sent_ts, report.clone()) {
+                    self.lastheard.insert(call.clone(), heard.clone());
+                }
+            }
+            Ok(Packet::Weather(report)) => {
+                let call = &report.src_callsign;
+                if let Some(pos) = &report.pos {
+                    let posreport = PositionReport {
+                        src_callsign: report.src_callsign.clone(),
+                        dst_callsign: None,
+                        pos: pos.clone(),
+                        symbol: Some(Symbol::WEATHER_STATION),
+                        altitude_m: None,
+                        comment: None,
+                        timestamp: report.timestamp,
+                    };
+                    if insert_if_newer(&mut self.lastpos, call, sent_ts, posreport) {
+                        self.lastheard.insert(call.clone(), heard.clone());
+                    }
+                }
+                insert_if_newer(&mut self.lastwx, call, sent_ts, report.clone());
+            }
+            Ok(Packet::Bulletin(msg)) => {
+                let key = (msg.src_callsign.clone(), msg.addressee.clone());
+                insert_if_newer(&mut self.bulletins, &key, ts, msg.clone());
+            }
+            Ok(Packet::Object(report)) => {
+                // Killed ones stay, so that older reports don't revive them
+                insert_if_newer(&mut self.objects, &report.name, sent_ts, report.clone());
+            }
+            Ok(Packet::Message(_)) | Err(_) => (),
         }
         let id = self.nextid;
         self.nextid += 1;
-        self.recent.push_back((id, ts, data, parsed));
+        self.first_heard.insert(key, (ts, id));
+        let pos = self.recent.partition_point(|e| e.ts <= ts);
+        self.recent.insert(
+            pos,
+            LogEntry {
+                id,
+                ts,
+                heard: vec![heard],
+                data,
+                packet: parsed,
+                dups: 0,
+            },
+        );
+        // Whatever is the oldest goes, even if it's the one we've just added
+        while self.recent.len() >= self.maxlen {
+            self.recent.pop_front();
+        }
         Ok(())
     }
 
@@ -207,7 +505,505 @@ impl Log {
         self.lastpos.values()
     }
 
-    pub fn recent_entries(&self) -> impl Iterator<Item = &(u64, Timestamp, String, Result<Packet>)> {
+    pub fn last_position(&self, callsign: &str) -> Option<&(Timestamp, PositionReport)> {
+        self.lastpos.get(callsign)
+    }
+
+    pub fn last_heard(&self, callsign: &str) -> Option<&Heard> {
+        self.lastheard.get(callsign)
+    }
+
+    /// Returns where the station has been lately, oldest first
+    pub fn track(&self, callsign: &str) -> impl Iterator<Item = &(Timestamp, Point)> {
+        self.tracks.get(callsign).into_iter().flatten()
+    }
+
+    fn add_to_track(&mut self, callsign: &str, ts: Timestamp, location: Point) {
+        let track = self.tracks.entry(callsign.to_string()).or_default();
+        let pos = track.partition_point(|(t, _)| *t <= ts);
+        // Parked stations keep beaconing the same spot, that's not a trail
+        if pos > 0 && track[pos - 1].1 == location {
+            return;
+        }
+        track.insert(pos, (ts, location));
+        while track.len() > TRACK_MAX_POINTS {
+            track.pop_front();
+        }
+    }
+
+    pub fn last_weather(&self) -> impl Iterator<Item = &(Timestamp, WeatherReport)> {
+        self.lastwx.values()
+    }
+
+    pub fn objects(&self) -> impl Iterator<Item = &(Timestamp, ObjectReport)> {
+        self.objects.values().filter(|(_, report)| report.alive)
+    }
+
+    pub fn bulletins(&self) -> impl Iterator<Item = &(Timestamp, Message)> {
+        self.bulletins.values()
+    }
+
+    /// Our callsign, so that what we transmit and hear back isn't logged
+    /// twice
+    pub fn set_own_callsign(&mut self, callsign: impl Into<String>) {
+        self.own_callsign = Some(callsign.into());
+    }
+
+    /// Checks if the packet is a copy of what we have sent recently and
+    /// remembers it otherwise
+    fn is_own_echo(&mut self, ts: Timestamp, data: &str) -> bool {
+        let own_callsign = match &self.own_callsign {
+            Some(v) => v,
+            None => return false,
+        };
+        let src = data.split('>').next().unwrap_or(data);
+        if !data.contains(':') || !src.eq_ignore_ascii_case(own_callsign) {
+            return false;
+        }
+        let key = dupe_key(data);
+        match self.own_sent.get(&key) {
+            Some(sent) if sent.duration_between(ts) < DUPE_WINDOW => true,
+            _ => {
+                self.own_sent.insert(key, ts);
+                false
+            }
+        }
+    }
+
+    /// Drop bulletins that haven't been re-sent for a while, and whatever
+    /// else is too old to matter
+    pub fn expire(&mut self, now: Timestamp) {
+        self.bulletins
+            .retain(|_, (ts, _)| ts.duration_between(now) < BULLETIN_TTL || *ts > now);
+        self.own_sent
+            .retain(|_, ts| ts.duration_between(now) < DUPE_WINDOW || *ts > now);
+        self.first_heard
+            .retain(|_, (ts, _)| ts.duration_between(now) < DUPE_WINDOW || *ts > now);
+        for track in self.tracks.values_mut() {
+            track.retain(|(ts, _)| ts.duration_between(now) < TRACK_MAX_AGE || *ts > now);
+        }
+        self.tracks.retain(|_, track| !track.is_empty());
+    }
+
+    pub fn recent_entries(&self) -> impl Iterator<Item = &LogEntry> {
         self.recent.iter()
     }
 }
+
+/// Inserts report unless there is a more recent one already, returns true
+/// if inserted
+fn insert_if_newer<K, V>(
+    map: &mut HashMap<K, (Timestamp, V)>,
+    key: &K,
+    ts: Timestamp,
+    value: V,
+) -> bool
+where
+    K: Clone + Eq + std::hash::Hash,
+{
+    match map.get(key) {
+        Some((last_ts, _)) if *last_ts > ts => false,
+        _ => {
+            map.insert(key.clone(), (ts, value));
+            true
+        }
+    }
+}
+
+/// Returns what identifies the packet, i.e. `SRC>DST:info` without the
+/// path, which changes as the packet travels
+pub fn dupe_key(data: &str) -> String {
+    match data.split_once(':') {
+        Some((header, info)) => {
+            let srcdst = header.split(',').next().unwrap_or(header);
+            format!("{}:{}", srcdst, info)
+        }
+        None => data.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_positionless_weather() {
+        let packet =
+            Packet::parse("BRCWX>APRS,WIDE2-1:_10090556c220s004g005t077r000p000P000h50b09900wRSW")
+                .unwrap();
+        match packet {
+            Packet::Weather(wx) => {
+                assert_eq!(wx.src_callsign, "BRCWX");
+                assert!(wx.pos.is_none());
+                assert_eq!(wx.wind_dir_deg, Some(220.));
+                assert_eq!(wx.wind_speed_mps.map(|v| v.round()), Some(2.));
+                assert_eq!(wx.temperature_c.map(|v| v.round()), Some(25.));
+                assert_eq!(wx.humidity_pct, Some(50.));
+                assert_eq!(wx.pressure_hpa, Some(990.));
+            }
+            _ => panic!("expected weather report, got {:?}", packet),
+        }
+    }
+
+    #[test]
+    fn test_parse_weather_with_position() {
+        let packet =
+            Packet::parse("BRCWX>APX,WIDE:!4046.40N/11912.12W_090/010g...t-05h00b10132").unwrap();
+        match packet {
+            Packet::Weather(wx) => {
+                assert!(wx.pos.is_some());
+                assert_eq!(wx.wind_dir_deg, Some(90.));
+                assert_eq!(wx.wind_gust_mps, None);
+                assert_eq!(wx.temperature_c.map(|v| v.round()), Some(-21.));
+                assert_eq!(wx.humidity_pct, Some(100.));
+                assert_eq!(wx.pressure_hpa, Some(1013.2));
+            }
+            _ => panic!("expected weather report, got {:?}", packet),
+        }
+    }
+
+    #[test]
+    fn test_log_keeps_weather_per_station() {
+        let mut log = Log::new();
+        let ts = Timestamp::from_calendar_utc(2023, 8, 30, 12, 0, 0).unwrap();
+        log.push(ts, "BRCWX>APX,WIDE:!4046.40N/11912.12W_090/010t080".into())
+            .unwrap();
+        log.push(ts, "BRCWX>APRS:_10090556c180s005t085".into())
+            .unwrap();
+        let wx = log.last_weather().collect::<Vec<_>>();
+        assert_eq!(wx.len(), 1);
+        assert_eq!(wx[0].1.wind_dir_deg, Some(180.));
+        assert!(log.last_position("BRCWX").is_some());
+    }
+
+    #[test]
+    fn test_parse_message() {
+        let packet = Packet::parse("W1AW>APRS::TGECKO   :Where are you?{123").unwrap();
+        match packet {
+            Packet::Message(msg) => {
+                assert_eq!(msg.src_callsign, "W1AW");
+                assert_eq!(msg.addressee, "TGECKO");
+                assert_eq!(msg.text, "Where are you?");
+                assert_eq!(msg.msgno.as_deref(), Some("123"));
+            }
+            _ => panic!("expected message, got {:?}", packet),
+        }
+
+        let packet = Packet::parse("W1AW>APRS::TGECKO-9 :Hi there{AB}CD").unwrap();
+        match packet {
+            Packet::Message(msg) => {
+                assert_eq!(msg.addressee, "TGECKO-9");
+                assert_eq!(msg.msgno.as_deref(), Some("AB"));
+            }
+            _ => panic!("expected message, got {:?}", packet),
+        }
+    }
+
+    #[test]
+    fn test_bulletins_expire() {
+        let mut log = Log::new();
+        let ts = Timestamp::from_calendar_utc(2023, 8, 30, 12, 0, 0).unwrap();
+        log.push(ts, "BRCWX>APRS::BLN1     :Dust storm incoming".into())
+            .unwrap();
+        log.push(ts, "BRCWX>APRS::BLN1     :Dust storm is here".into())
+            .unwrap();
+        let bulletins = log.bulletins().collect::<Vec<_>>();
+        assert_eq!(bulletins.len(), 1);
+        assert_eq!(bulletins[0].1.text, "Dust storm is here");
+
+        log.expire(ts.saturating_add(Duration::from_secs(3600)));
+        assert_eq!(log.bulletins().count(), 1);
+        log.expire(ts.saturating_add(BULLETIN_TTL));
+        assert_eq!(log.bulletins().count(), 0);
+    }
+
+    #[test]
+    fn test_objects_are_keyed_by_name() {
+        let mut log = Log::new();
+        let ts = Timestamp::from_calendar_utc(2023, 8, 30, 12, 0, 0).unwrap();
+        log.push(
+            ts,
+            "W1AW>APRS:;CAMP X   *092345z4046.40N/11912.12W-Camp".into(),
+        )
+        .unwrap();
+        log.push(ts, "W1AW>APRS:)AID#2!4046.50N/11912.10WA".into())
+            .unwrap();
+        assert!(log.last_position("W1AW").is_none());
+        let mut names = log
+            .objects()
+            .map(|(_, obj)| (obj.name.as_str(), obj.kind))
+            .collect::<Vec<_>>();
+        names.sort_by_key(|v| v.0);
+        assert_eq!(
+            names,
+            vec![("AID#2", ObjectKind::Item), ("CAMP X", ObjectKind::Object)]
+        );
+
+        log.push(
+            ts,
+            "W1AW>APRS:;CAMP X   _092345z4046.40N/11912.12W-Camp".into(),
+        )
+        .unwrap();
+        assert_eq!(log.objects().count(), 1);
+    }
+
+    #[test]
+    fn test_out_of_order() {
+        let mut log = Log::new();
+        let t0 = Timestamp::from_calendar_utc(2023, 8, 30, 12, 0, 0).unwrap();
+        let t1 = Timestamp::from_calendar_utc(2023, 8, 30, 12, 1, 0).unwrap();
+        let t2 = Timestamp::from_calendar_utc(2023, 8, 30, 12, 2, 0).unwrap();
+
+        log.push(t2, "TGECKO>APDR15:!4046.40N/11912.12W>".into())
+            .unwrap();
+        log.push(t0, "TGECKO>APDR15:!4000.00N/11912.00W>".into())
+            .unwrap();
+        log.push(t1, "DUCK>APRS:>hi".into()).unwrap();
+        let times = log.recent_entries().map(|e| e.ts).collect::<Vec<_>>();
+        assert_eq!(times, vec![t0, t1, t2]);
+        let (ts, report) = log.last_position("TGECKO").unwrap();
+        assert_eq!(*ts, t2);
+        assert_eq!(report.pos.location.lat().round(), 41.);
+
+        // Older report doesn't bring a killed object back
+        log.push(t2, "W1AW>APRS:;CAMP X   _092345z4046.40N/11912.12W-".into())
+            .unwrap();
+        log.push(t1, "W1AW>APRS:;CAMP X   *092345z4046.40N/11912.12W-".into())
+            .unwrap();
+        assert_eq!(log.objects().count(), 0);
+    }
+
+    #[test]
+    fn test_packet_time() {
+        let mut log = Log::new();
+        let rx = Timestamp::from_calendar_utc(2023, 8, 30, 12, 0, 0).unwrap();
+        let sent = Timestamp::from_calendar_utc(2023, 8, 30, 11, 52, 0).unwrap();
+        log.push(rx, "TGECKO>APDR15:/301152z4046.40N/11912.12W>".into())
+            .unwrap();
+        assert_eq!(log.last_position("TGECKO").unwrap().0, sent);
+        assert_eq!(log.recent_entries().next().unwrap().ts, rx);
+
+        // Bogus timestamps are ignored, so are small clock differences
+        log.push(rx, "DUCK>APRS:@010000z4046.40N/11912.12W>".into())
+            .unwrap();
+        assert_eq!(log.last_position("DUCK").unwrap().0, rx);
+        log.push(rx, "PEEF>APRS:@120130h4046.40N/11912.12W>".into())
+            .unwrap();
+        assert_eq!(log.last_position("PEEF").unwrap().0, rx);
+    }
+
+    #[test]
+    fn test_track() {
+        let mut log = Log::new();
+        let t0 = Timestamp::from_calendar_utc(2023, 8, 30, 21, 0, 0).unwrap();
+        let at = |mins: u64| t0.add(Duration::from_secs(mins * 60)).unwrap();
+        for (mins, pos) in [
+            (0, "4046.40N/11912.12W"),
+            (2, "4046.50N/11912.20W"),
+            (4, "4046.50N/11912.20W"),
+            (1, "4046.45N/11912.16W"),
+        ] {
+            let line = format!("DUCK>APRS:!{}>", pos);
+            log.push(at(mins), line).unwrap();
+        }
+        let times = log.track("DUCK").map(|(ts, _)| *ts).collect::<Vec<_>>();
+        assert_eq!(times, vec![at(0), at(1), at(2)]);
+        assert_eq!(log.track("TGECKO").count(), 0);
+
+        log.expire(t0.add(TRACK_MAX_AGE).unwrap());
+        assert_eq!(log.track("DUCK").count(), 2);
+        log.expire(at(3).add(TRACK_MAX_AGE).unwrap());
+        assert_eq!(log.track("DUCK").count(), 0);
+    }
+
+    #[test]
+    fn test_position_keeps_symbol_and_altitude() {
+        let packet =
+            Packet::parse("TGECKO>APDR15,WIDE1-1:=4046.40N/11912.12W>088/036/A=003904 Gecko")
+                .unwrap();
+        match packet {
+            Packet::Position(pr) => {
+                assert_eq!(pr.symbol, Some(Symbol::new('/', '>')));
+                assert_eq!(pr.dst_callsign.as_deref(), Some("APDR15"));
+                assert_eq!(pr.altitude_m.map(|v| v.round()), Some(1190.));
+            }
+            _ => panic!("expected position report, got {:?}", packet),
+        }
+
+        let packet = Packet::parse("PEEF>APRS:!/5L!!<*e7[  sT").unwrap();
+        match packet {
+            Packet::Position(pr) => assert_eq!(pr.symbol, Some(Symbol::new('/', '['))),
+            _ => panic!("expected position report, got {:?}", packet),
+        }
+    }
+
+    #[test]
+    fn test_symbol_id_roundtrip() {
+        let sym = Symbol::new('\\', '_');
+        assert_eq!(sym.id(), "5c5f");
+        assert_eq!(Symbol::from_id("5c5f").unwrap(), sym);
+        assert!(Symbol::from_id("zz").is_err());
+        assert!(Symbol::from_id("2f00").is_err());
+    }
+
+    #[test]
+    fn test_own_echoes_are_ignored() {
+        let mut log = Log::new();
+        log.set_own_callsign("K6CQU-5");
+        let t0 = Timestamp::now();
+        let t1 = t0.add(Duration::from_secs(5)).unwrap();
+        let t2 = t0.add(DUPE_WINDOW).unwrap();
+
+        log.push(t0, "K6CQU-5>APZLPK,WIDE1-1:!4046.40N/11912.12W-".into())
+            .unwrap();
+        log.push(
+            t1,
+            "K6CQU-5>APZLPK,K6CQU-4*,WIDE1*:!4046.40N/11912.12W-".into(),
+        )
+        .unwrap();
+        log.push(t1, "K6CQU-4>APZLPK,WIDE1-1:!4046.40N/11912.12W-".into())
+            .unwrap();
+        assert_eq!(log.recent_entries().count(), 2);
+
+        // Same beacon sent again later is not an echo
+        log.push(t2, "K6CQU-5>APZLPK,WIDE1-1:!4046.40N/11912.12W-".into())
+            .unwrap();
+        assert_eq!(log.recent_entries().count(), 3);
+    }
+
+    #[test]
+    fn test_duplicates_are_merged() {
+        let mut log = Log::new();
+        let t0 = Timestamp::now();
+        let t1 = t0.add(Duration::from_secs(5)).unwrap();
+        let t2 = t0.add(DUPE_WINDOW).unwrap();
+
+        log.push(t0, "TGECKO>APDR15,WIDE1-1:!4046.40N/11912.12W>".into())
+            .unwrap();
+        log.push(
+            t1,
+            "TGECKO>APDR15,K6CQU-4*,WIDE1*:!4046.40N/11912.12W>".into(),
+        )
+        .unwrap();
+        log.push(
+            t1,
+            "TGECKO>APDR15,TCPIP*,qAC,T2TEXAS:!4046.40N/11912.12W>".into(),
+        )
+        .unwrap();
+        let entries = log.recent_entries().collect::<Vec<_>>();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].dups, 2);
+        let paths = entries[0]
+            .heard
+            .iter()
+            .map(|h| h.path.join(","))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec!["WIDE1-1", "K6CQU-4*,WIDE1*", "TCPIP*,qAC,T2TEXAS"]
+        );
+        assert_eq!(log.last_position("TGECKO").unwrap().0, t0);
+
+        // Different payload or too late is a new packet
+        log.push(t1, "TGECKO>APDR15,WIDE1-1:>hi".into()).unwrap();
+        log.push(t2, "TGECKO>APDR15,WIDE1-1:!4046.40N/11912.12W>".into())
+            .unwrap();
+        assert_eq!(log.recent_entries().count(), 3);
+    }
+
+    #[test]
+    fn test_heard() {
+        let heard = |data: &str| Heard::new(Some("/dev/ttyUSB0".into()), data);
+        assert_eq!(heard("DUCK>APRS:>hi").digipeater(), None);
+        assert_eq!(heard("DUCK>APRS,WIDE1-1,WIDE2-1:>hi").digipeater(), None);
+        assert_eq!(
+            heard("DUCK>APRS,K6CQU-4*,WIDE1*,WIDE2-1:>hi").digipeater(),
+            Some("K6CQU-4")
+        );
+        assert_eq!(
+            heard("DUCK>APRS,K6CQU-4,BRC*,WIDE2*:>hi").digipeater(),
+            Some("BRC")
+        );
+        assert_eq!(heard("DUCK>APRS,TCPIP*,qAC,T2TEXAS:>hi").digipeater(), None);
+        assert!(heard("DUCK>APRS,K6CQU-4*,WIDE1*,WIDE2-1:>hi").is_rf());
+        assert!(!heard("DUCK>APRS,TCPIP*,qAC,T2TEXAS:>hi").is_rf());
+        assert!(!heard("DUCK>APRS,K6CQU-4*,qAR,K6CQU-4:>hi").is_rf());
+        assert_eq!(
+            heard("DUCK>APRS,WIDE1-1:>hi").to_string(),
+            "/dev/ttyUSB0 via WIDE1-1"
+        );
+        assert_eq!(
+            Heard::new(None, "DUCK>APRS:>hi").to_string(),
+            "? via direct"
+        );
+
+        let mut log = Log::new();
+        let t0 = Timestamp::now();
+        log.push_from(
+            t0,
+            Some("rotate.aprs2.net:14580".into()),
+            "DUCK>APRS,K6CQU-4*,WIDE1*,qAR,K6CQU-5:!4046.40N/11912.12W>".into(),
+        )
+        .unwrap();
+        let heard = log.last_heard("DUCK").unwrap();
+        assert_eq!(heard.source.as_deref(), Some("rotate.aprs2.net:14580"));
+        assert_eq!(heard.digipeater(), Some("K6CQU-4"));
+    }
+
+    #[test]
+    fn test_outbox() {
+        let t0 = Timestamp::now();
+        let mut outbox = Outbox::new();
+        assert!(outbox.queue("K6CQU-5", "TGECKO", "", t0).is_err());
+        assert!(outbox.queue("K6CQU-5", "TGECKO", "a{b", t0).is_err());
+        assert!(outbox.queue("K6CQU-5", "NOT A CALL", "hi", t0).is_err());
+        let msgno = outbox
+            .queue("K6CQU-5", "tgecko", "Meet at 6:00 & Esplanade", t0)
+            .unwrap()
+            .msg
+            .msgno
+            .clone()
+            .unwrap();
+
+        let due = outbox.due(t0);
+        assert_eq!(due.len(), 1);
+        assert_eq!(
+            due[0].to_info(),
+            format!(":TGECKO   :Meet at 6:00 & Esplanade{{{}", msgno)
+        );
+        assert!(outbox
+            .due(t0.add(Duration::from_secs(10)).unwrap())
+            .is_empty());
+        assert_eq!(outbox.due(t0.add(Outbox::RETRY_DELAY).unwrap()).len(), 1);
+
+        // Ack as it comes back from the air
+        let packet = format!("TGECKO>APDR15,WIDE1-1::K6CQU-5  :ack{}", msgno);
+        let ack = match Packet::parse(packet) {
+            Ok(Packet::Message(msg)) => msg,
+            res => panic!("expected message, got {:?}", res),
+        };
+        assert_eq!(ack.reply(), Some((true, msgno.as_str())));
+        assert!(outbox.handle_reply(&ack.src_callsign, &msgno, true));
+        assert_eq!(outbox.entries().next().unwrap().state, DeliveryState::Acked);
+        assert!(outbox
+            .due(t0.add(Duration::from_secs(3600)).unwrap())
+            .is_empty());
+    }
+
+    #[test]
+    fn test_outbox_gives_up() {
+        let mut now = Timestamp::now();
+        let mut outbox = Outbox::new();
+        outbox.queue("K6CQU-5", "DUCK", "anyone?", now).unwrap();
+        let mut sent = 0;
+        for _ in 0..100 {
+            sent += outbox.due(now).len();
+            now = now.add(Duration::from_secs(30)).unwrap();
+        }
+        assert_eq!(sent as u32, Outbox::MAX_ATTEMPTS);
+        assert_eq!(
+            outbox.entries().next().unwrap().state,
+            DeliveryState::Failed
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Message {
+    pub src_callsign: String,
+    pub addressee: String,
+    pub text: String,
+    pub msgno: Option<String>,
+}
+
+impl Message {
+    /// Parses message body, i.e. `:ADDRESSEE:text{msgno`
+    pub fn parse(src_callsign: &str, body: &str) -> Option<Self> {
+        let body = body.strip_prefix(':')?;
+        let addressee = body.get(0..9)?.trim_end();
+        let rest = body.get(9..)?.strip_prefix(':')?;
+        if addressee.is_empty() {
+            return None;
+        }
+        let (text, msgno) = match rest.rsplit_once('{') {
+            // Reply-ack capable stations send {MM}AA, we only care about MM
+            Some((text, id)) if !id.is_empty() && id.len() <= 8 => {
+                let id = id.split('}').next().unwrap_or(id);
+                (text, Some(id.trim().to_string()))
+            }
+            _ => (rest, None),
+        };
+        Some(Self {
+            src_callsign: src_callsign.into(),
+            addressee: addressee.into(),
+            text: text.trim_end().into(),
+            msgno,
+        })
+    }
+
+    /// Ack or rej for the message we've sent, i.e. `(accepted, msgno)`
+    pub fn reply(&self) -> Option<(bool, &str)> {
+        if self.msgno.is_some() {
+            return None;
+        }
+        let (accepted, msgno) = if let Some(v) = self.text.strip_prefix("ack") {
+            (true, v)
+        } else if let Some(v) = self.text.strip_prefix("rej") {
+            (false, v)
+        } else {
+            return None;
+        };
+        // Reply-ack capable stations send ackMM}AA
+        let msgno = msgno.split('}').next().unwrap_or(msgno).trim();
+        (!msgno.is_empty() && msgno.len() <= 5).then_some((accepted, msgno))
+    }
+
+    /// Ack we send in response to this message, if it asks for one
+    pub fn ack(&self, src_callsign: &str) -> Option<Message> {
+        Some(Message {
+            src_callsign: src_callsign.into(),
+            addressee: self.src_callsign.clone(),
+            text: format!("ack{}", self.msgno.as_ref()?),
+            msgno: None,
+        })
+    }
+
+    /// Formats message body, i.e. the reverse of `parse`
+    pub fn to_info(&self) -> String {
+        match &self.msgno {
+            Some(msgno) => format!(":{:<9}:{}{{{}", self.addressee, self.text, msgno),
+            None => format!(":{:<9}:{}", self.addressee, self.text),
+        }
+    }
+
+    /// Bulletins and announcements are sent to BLN0..BLN9, BLNA..BLNZ or
+    /// BLN#GROUP pseudo-addressees
+    pub fn is_bulletin(&self) -> bool {
+        self.addressee.starts_with("BLN")
+    }
+}
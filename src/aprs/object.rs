@@ -0,0 +1,53 @@
+use super::{PacketTime, Symbol};
+use crate::motion::Position;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ObjectKind {
+    Object,
+    Item,
+}
+
+/// Position of a named thing reported on its behalf by some other station
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ObjectReport {
+    pub src_callsign: String,
+    pub name: String,
+    pub kind: ObjectKind,
+    pub alive: bool,
+    pub pos: Position,
+    pub symbol: Option<Symbol>,
+    pub comment: Option<String>,
+    pub timestamp: Option<PacketTime>,
+}
+
+impl ObjectReport {
+    /// Extracts object or item name and "alive" flag from the packet body, i.e.
+    /// `;NAME_____*...` for objects or `)NAME!...` for items.
+    pub fn parse_name(body: &str) -> Option<(ObjectKind, String, bool)> {
+        if let Some(rest) = body.strip_prefix(';') {
+            let name = rest.get(0..9)?.trim_end();
+            let alive = match rest.get(9..10)? {
+                "*" => true,
+                "_" => false,
+                _ => return None,
+            };
+            (!name.is_empty()).then(|| (ObjectKind::Object, name.to_string(), alive))
+        } else if let Some(rest) = body.strip_prefix(')') {
+            let end = rest.find(['!', '_']).filter(|&n| (3..=9).contains(&n))?;
+            let alive = &rest[end..end + 1] == "!";
+            Some((ObjectKind::Item, rest[..end].trim_end().to_string(), alive))
+        } else {
+            None
+        }
+    }
+
+    pub fn slug(&self) -> String {
+        format!(
+            "aprs/obj/{}",
+            self.name.to_ascii_lowercase().replace(' ', "-")
+        )
+    }
+}
@@ -0,0 +1,163 @@
+use super::Message;
+use crate::{
+    err::{Error, Result},
+    util::time::{Duration, Timestamp},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DeliveryState {
+    /// Waiting for (re)transmission or ack
+    Pending,
+    Acked,
+    Rejected,
+    /// Gave up retrying
+    Failed,
+}
+
+#[derive(Debug, Clone)]
+pub struct OutboxEntry {
+    pub id: u64,
+    pub msg: Message,
+    pub queued: Timestamp,
+    pub attempts: u32,
+    pub last_attempt: Option<Timestamp>,
+    pub state: DeliveryState,
+}
+
+/// Messages we send, retried until acked, rejected or given up on
+#[derive(Debug, Clone)]
+pub struct Outbox {
+    entries: VecDeque<OutboxEntry>,
+    nextid: u64,
+}
+
+impl Outbox {
+    /// Delay before the first retry, doubles with every attempt after that
+    pub const RETRY_DELAY: Duration = Duration::from_secs(30);
+    pub const MAX_ATTEMPTS: u32 = 5;
+    /// Max number of entries to keep, delivered or not
+    pub const MAX_ENTRIES: usize = 50;
+    pub const MAX_TEXT_LEN: usize = 67;
+
+    pub fn new() -> Self {
+        Self {
+            entries: VecDeque::new(),
+            nextid: 1,
+        }
+    }
+
+    pub fn queue(
+        &mut self,
+        src_callsign: &str,
+        addressee: &str,
+        text: &str,
+        now: Timestamp,
+    ) -> Result<&OutboxEntry> {
+        let addressee = addressee.trim().to_ascii_uppercase();
+        let text = text.trim();
+        if addressee.is_empty()
+            || addressee.len() > 9
+            || !addressee
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-')
+        {
+            return Err(Error::BadRequest(format!("bad callsign {:?}", addressee)));
+        }
+        if text.is_empty() || text.chars().count() > Self::MAX_TEXT_LEN {
+            return Err(Error::BadRequest(format!(
+                "message must be 1 to {} characters long",
+                Self::MAX_TEXT_LEN
+            )));
+        }
+        // These have special meaning in message format
+        if text.contains(['|', '~', '{']) || !text.chars().all(|c| (' '..='~').contains(&c)) {
+            return Err(Error::BadRequest(
+                "message may only contain printable ASCII characters except | ~ {".into(),
+            ));
+        }
+
+        while self.entries.len() >= Self::MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+        let id = self.nextid;
+        self.nextid += 1;
+        self.entries.push_back(OutboxEntry {
+            id,
+            msg: Message {
+                src_callsign: src_callsign.into(),
+                addressee,
+                text: text.into(),
+                // Message numbers are up to 5 alphanumeric characters
+                msgno: Some((id % 100000).to_string()),
+            },
+            queued: now,
+            attempts: 0,
+            last_attempt: None,
+            state: DeliveryState::Pending,
+        });
+        Ok(self.entries.back().unwrap())
+    }
+
+    /// Returns messages that need to be (re)transmitted now, gives up on the
+    /// ones that have been retried enough
+    pub fn due(&mut self, now: Timestamp) -> Vec<Message> {
+        let mut res = vec![];
+        for entry in self.entries.iter_mut() {
+            if entry.state != DeliveryState::Pending {
+                continue;
+            }
+            let due = match entry.last_attempt {
+                None => true,
+                Some(ts) => {
+                    let delay = Self::RETRY_DELAY * 2u32.pow(entry.attempts.saturating_sub(1));
+                    now >= ts && ts.duration_between(now) >= delay
+                }
+            };
+            if !due {
+                continue;
+            }
+            if entry.attempts >= Self::MAX_ATTEMPTS {
+                entry.state = DeliveryState::Failed;
+                continue;
+            }
+            entry.attempts += 1;
+            entry.last_attempt = Some(now);
+            res.push(entry.msg.clone());
+        }
+        res
+    }
+
+    /// Handles ack or rej from the addressee, returns true if it was for one
+    /// of our pending messages
+    pub fn handle_reply(&mut self, from: &str, msgno: &str, accepted: bool) -> bool {
+        let entry = self.entries.iter_mut().find(|e| {
+            e.state == DeliveryState::Pending
+                && e.msg.addressee.eq_ignore_ascii_case(from)
+                && e.msg.msgno.as_deref() == Some(msgno)
+        });
+        match entry {
+            Some(entry) => {
+                entry.state = if accepted {
+                    DeliveryState::Acked
+                } else {
+                    DeliveryState::Rejected
+                };
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = &OutboxEntry> {
+        self.entries.iter()
+    }
+}
+
+impl Default for Outbox {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,192 @@
+use crate::err::{Error, Result};
+
+/// APRS symbol as a pair of symbol table (or overlay) and symbol code characters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol {
+    pub table: char,
+    pub code: char,
+}
+
+impl Symbol {
+    pub const PRIMARY_TABLE: char = '/';
+    pub const ALTERNATE_TABLE: char = '\\';
+    pub const WEATHER_STATION: Symbol = Symbol::new('/', '_');
+
+    pub const fn new(table: char, code: char) -> Self {
+        Self { table, code }
+    }
+
+    /// Finds symbol table and code in position, object, item or Mic-E packet body
+    pub fn parse(body: &str) -> Option<Self> {
+        let bytes = body.as_bytes();
+        let (table_idx, code_idx) = match *bytes.first()? {
+            b'!' | b'=' => Self::position_offsets(bytes, 1)?,
+            b'/' | b'@' => Self::position_offsets(bytes, 8)?,
+            b';' => Self::position_offsets(bytes, 18)?,
+            b')' => {
+                let end = body.find(['!', '_'])?;
+                Self::position_offsets(bytes, end + 1)?
+            }
+            b'`' | b'\'' => (8, 7),
+            _ => return None,
+        };
+        let sym = Self::new(
+            *bytes.get(table_idx)? as char,
+            *bytes.get(code_idx)? as char,
+        );
+        sym.is_valid().then_some(sym)
+    }
+
+    /// Returns (table, code) indices for position that starts at given offset
+    fn position_offsets(bytes: &[u8], offset: usize) -> Option<(usize, usize)> {
+        if bytes.get(offset)?.is_ascii_digit() {
+            Some((offset + 8, offset + 18)) // uncompressed
+        } else {
+            Some((offset, offset + 9)) // compressed
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        let table_ok = self.table == Self::PRIMARY_TABLE
+            || self.table == Self::ALTERNATE_TABLE
+            || self.table.is_ascii_digit()
+            || self.table.is_ascii_uppercase();
+        table_ok && ('!'..='~').contains(&self.code)
+    }
+
+    /// Overlay character for alternate table symbols, if any
+    pub fn overlay(&self) -> Option<char> {
+        (self.table != Self::PRIMARY_TABLE && self.table != Self::ALTERNATE_TABLE)
+            .then_some(self.table)
+    }
+
+    /// URL-safe symbol identifier, i.e. hex codes of table and symbol characters
+    pub fn id(&self) -> String {
+        format!("{:02x}{:02x}", self.table as u8, self.code as u8)
+    }
+
+    pub fn from_id(id: &str) -> Result<Self> {
+        let bad_id = || Error::BadRequest(format!("bad symbol id: {}", id));
+        if id.len() != 4 || !id.is_ascii() {
+            return Err(bad_id());
+        }
+        let table = u8::from_str_radix(&id[0..2], 16).map_err(|_| bad_id())?;
+        let code = u8::from_str_radix(&id[2..4], 16).map_err(|_| bad_id())?;
+        let sym = Self::new(table as char, code as char);
+        if sym.is_valid() {
+            Ok(sym)
+        } else {
+            Err(bad_id())
+        }
+    }
+
+    /// Renders map icon for this symbol
+    pub fn icon_svg(&self) -> String {
+        let primary = self.table == Self::PRIMARY_TABLE;
+        let (glyph, color) = match (primary, self.code) {
+            (_, '>') => ("🚗", VEHICLE),
+            (_, '[') => ("🚶", PERSON),
+            (true, 'b') => ("🚲", PERSON),
+            (true, '<') => ("🏍", VEHICLE),
+            (true, 'j') | (false, 'k') => ("🚙", VEHICLE),
+            (true, 'k') | (_, 'u') => ("🚚", VEHICLE),
+            (_, 'v') | (true, 'R') => ("🚐", VEHICLE),
+            (true, 'U') => ("🚌", VEHICLE),
+            (true, '=') => ("🚆", VEHICLE),
+            (true, 'a') => ("🚑", MEDICAL),
+            (true, 'f') => ("🚒", MEDICAL),
+            (true, 'h') => ("🏥", MEDICAL),
+            (true, '+') => ("➕", MEDICAL),
+            (_, '_') | (_, 'W') => ("⛅", WEATHER),
+            (_, '-') => ("🏠", PLACE),
+            (true, ';') => ("⛺", PLACE),
+            (false, 'r') => ("🚻", PLACE),
+            (true, '\'') | (_, '^') => ("✈", AIR),
+            (true, 'X') => ("🚁", AIR),
+            (true, 'O') => ("🎈", AIR),
+            (true, 'Y') => ("⛵", WATER),
+            (_, 's') => ("🚤", WATER),
+            (_, '#') | (_, '&') | (true, 'r') | (true, 'n') => ("📡", OTHER),
+            (false, '?') => ("ℹ", OTHER),
+            _ => ("", OTHER),
+        };
+        let label = if glyph.is_empty() {
+            xml_escape(self.code)
+        } else {
+            glyph.to_string()
+        };
+        let overlay = match self.overlay() {
+            Some(c) => format!(
+                "<text x='26' y='28' font-size='11' font-family='sans-serif' font-weight='bold' \
+                 fill='#fff' text-anchor='middle'>{}</text>",
+                c
+            ),
+            None => String::new(),
+        };
+        format!(
+            "<svg xmlns='http://www.w3.org/2000/svg' width='32' height='32' viewBox='0 0 32 32'>\
+             <circle cx='16' cy='16' r='14' fill='{}' stroke='#fff' stroke-width='2'/>\
+             <text x='16' y='21' font-size='15' font-family='sans-serif' fill='#fff' \
+             text-anchor='middle'>{}</text>{}</svg>",
+            color, label, overlay
+        )
+    }
+}
+
+const VEHICLE: &str = "#3a7bd5";
+const PERSON: &str = "#e67e22";
+const MEDICAL: &str = "#c0392b";
+const WEATHER: &str = "#16a085";
+const PLACE: &str = "#7f8c8d";
+const AIR: &str = "#8e44ad";
+const WATER: &str = "#2980b9";
+const OTHER: &str = "#2c3e50";
+
+fn xml_escape(c: char) -> String {
+    match c {
+        '<' => "&lt;".into(),
+        '>' => "&gt;".into(),
+        '&' => "&amp;".into(),
+        '\'' => "&apos;".into(),
+        '"' => "&quot;".into(),
+        c => c.to_string(),
+    }
+}
+
+impl std::fmt::Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.table, self.code)
+    }
+}
+
+impl serde::Serialize for Symbol {
+    fn serialize<S>(&self, ser: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        ser.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Symbol {
+    fn deserialize<D>(deser: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let v = <String>::deserialize(deser)?;
+        v.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl std::str::FromStr for Symbol {
+    type Err = Error;
+
+    /// Parses table and code characters, e.g. `/>`
+    fn from_str(v: &str) -> Result<Self> {
+        let mut chars = v.chars();
+        match (chars.next(), chars.next(), chars.next()) {
+            (Some(table), Some(code), None) => Ok(Self::new(table, code)),
+            _ => Err(Error::BadRequest(format!("bad APRS symbol: {}", v))),
+        }
+    }
+}
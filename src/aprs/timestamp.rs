@@ -0,0 +1,191 @@
+use crate::util::time::{Duration, Timestamp};
+use serde::{Deserialize, Serialize};
+
+/// Packets claiming to be older than this are more likely from a tracker
+/// with a bad clock (or no GPS fix yet) than delayed this much
+pub const MAX_DELAY: Duration = Duration::from_secs(2 * 3600);
+
+/// How far ahead of ours a sender's clock can be
+pub const MAX_AHEAD: Duration = Duration::from_secs(5 * 60);
+
+/// Time the sender put in the packet, UTC; it never says the year and often
+/// not the month or the day, so it only makes sense next to receive time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PacketTime {
+    /// `DDHHMMz`
+    DayHourMinute { day: u8, hour: u8, minute: u8 },
+    /// `HHMMSSh`
+    HourMinuteSecond { hour: u8, minute: u8, second: u8 },
+    /// `MMDDHHMM`, as in positionless weather reports
+    MonthDayHourMinute {
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+    },
+}
+
+impl PacketTime {
+    /// Extracts timestamp from the packet body if it has one. Local time
+    /// (`DDHHMM/`) is ignored, as we don't know the sender's time zone.
+    pub fn parse(body: &str) -> Option<Self> {
+        let (digits, suffix) = match body.chars().next()? {
+            '/' | '@' => (body.get(1..7)?, body.get(7..8)?),
+            ';' => (body.get(11..17)?, body.get(17..18)?),
+            '_' => (body.get(1..9)?, ""),
+            _ => return None,
+        };
+        if !digits.bytes().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let num = |i: usize| digits[i..i + 2].parse::<u8>().unwrap_or_default();
+        let res = match suffix {
+            "" => Self::MonthDayHourMinute {
+                month: num(0),
+                day: num(2),
+                hour: num(4),
+                minute: num(6),
+            },
+            "z" => Self::DayHourMinute {
+                day: num(0),
+                hour: num(2),
+                minute: num(4),
+            },
+            "h" => Self::HourMinuteSecond {
+                hour: num(0),
+                minute: num(2),
+                second: num(4),
+            },
+            _ => return None,
+        };
+        Some(res)
+    }
+
+    /// Returns the most recent moment matching the timestamp, unless it's
+    /// too far from when the packet was received to be believable
+    pub fn resolve(self, rx_ts: Timestamp) -> Option<Timestamp> {
+        let latest = rx_ts.saturating_add(MAX_AHEAD);
+        let dt = ::time::OffsetDateTime::from(latest);
+        let prev_day = dt - ::time::Duration::DAY;
+        let (prev_month_year, prev_month) = match dt.month() {
+            ::time::Month::January => (dt.year() - 1, ::time::Month::December),
+            month => (dt.year(), month.previous()),
+        };
+        let candidates = match self {
+            Self::DayHourMinute { day, hour, minute } => [
+                (dt.year(), dt.month(), day, hour, minute, 0),
+                (prev_month_year, prev_month, day, hour, minute, 0),
+            ],
+            Self::HourMinuteSecond {
+                hour,
+                minute,
+                second,
+            } => [
+                (dt.year(), dt.month(), dt.day(), hour, minute, second),
+                (
+                    prev_day.year(),
+                    prev_day.month(),
+                    prev_day.day(),
+                    hour,
+                    minute,
+                    second,
+                ),
+            ],
+            Self::MonthDayHourMinute {
+                month,
+                day,
+                hour,
+                minute,
+            } => {
+                let month = ::time::Month::try_from(month).ok()?;
+                [
+                    (dt.year(), month, day, hour, minute, 0),
+                    (dt.year() - 1, month, day, hour, minute, 0),
+                ]
+            }
+        };
+        let ts = candidates
+            .into_iter()
+            .filter_map(|(y, m, d, h, min, s)| {
+                Timestamp::from_calendar_utc(y as u16, m as u8, d, h, min, s).ok()
+            })
+            .find(|ts| *ts <= latest)?;
+        (ts > rx_ts || ts.duration_between(rx_ts) <= MAX_DELAY).then_some(ts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            PacketTime::parse("/092345z4046.40N/11912.12W>"),
+            Some(PacketTime::DayHourMinute {
+                day: 9,
+                hour: 23,
+                minute: 45
+            })
+        );
+        assert_eq!(
+            PacketTime::parse("@234517h4046.40N/11912.12W>"),
+            Some(PacketTime::HourMinuteSecond {
+                hour: 23,
+                minute: 45,
+                second: 17
+            })
+        );
+        assert_eq!(
+            PacketTime::parse(";CAMP X   *092345z4046.40N/11912.12W-"),
+            Some(PacketTime::DayHourMinute {
+                day: 9,
+                hour: 23,
+                minute: 45
+            })
+        );
+        assert_eq!(
+            PacketTime::parse("_08291700c270s005g010t090"),
+            Some(PacketTime::MonthDayHourMinute {
+                month: 8,
+                day: 29,
+                hour: 17,
+                minute: 0
+            })
+        );
+        assert_eq!(PacketTime::parse("/092345/4046.40N/11912.12W>"), None);
+        assert_eq!(PacketTime::parse("!4046.40N/11912.12W>"), None);
+        assert_eq!(PacketTime::parse("/09x345z4046.40N/11912.12W>"), None);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let rx = Timestamp::from_calendar_utc(2023, 9, 1, 0, 10, 0).unwrap();
+        let resolve = |s: &str| PacketTime::parse(s).unwrap().resolve(rx);
+
+        // Previous day and month
+        assert_eq!(
+            resolve("/312359z"),
+            Some(Timestamp::from_calendar_utc(2023, 8, 31, 23, 59, 0).unwrap())
+        );
+        assert_eq!(
+            resolve("/235930h"),
+            Some(Timestamp::from_calendar_utc(2023, 8, 31, 23, 59, 30).unwrap())
+        );
+        assert_eq!(
+            resolve("_08312359"),
+            Some(Timestamp::from_calendar_utc(2023, 8, 31, 23, 59, 0).unwrap())
+        );
+        // Sender's clock is a bit ahead
+        assert_eq!(
+            resolve("/010012z"),
+            Some(Timestamp::from_calendar_utc(2023, 9, 1, 0, 12, 0).unwrap())
+        );
+        // Too old or too far ahead
+        assert_eq!(resolve("/311900z"), None);
+        assert_eq!(resolve("/210000h"), None);
+        assert_eq!(resolve("/011000z"), None);
+        assert_eq!(resolve("/320000z"), None);
+    }
+}
@@ -0,0 +1,109 @@
+use super::PacketTime;
+use crate::motion::Position;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WeatherReport {
+    pub src_callsign: String,
+    pub pos: Option<Position>,
+    pub wind_dir_deg: Option<f64>,
+    pub wind_speed_mps: Option<f64>,
+    pub wind_gust_mps: Option<f64>,
+    pub temperature_c: Option<f64>,
+    pub humidity_pct: Option<f64>,
+    pub pressure_hpa: Option<f64>,
+    pub timestamp: Option<PacketTime>,
+}
+
+impl WeatherReport {
+    /// Parses positionless weather report body, i.e. `_MMDDHHMMc...s...g...t...`
+    pub fn parse_positionless(src_callsign: &str, body: &str) -> Option<Self> {
+        let data = body.strip_prefix('_')?.get(8..)?;
+        let mut report = Self {
+            src_callsign: src_callsign.into(),
+            ..Default::default()
+        };
+        report.parse_fields(data);
+        Some(report)
+    }
+
+    /// Parses weather data that follows position and `_` symbol in a complete
+    /// weather report body. Position itself is expected to be already decoded.
+    pub fn parse_with_position(src_callsign: &str, body: &str, pos: Position) -> Option<Self> {
+        let offset = match body.chars().next()? {
+            '!' | '=' => 1,
+            '/' | '@' => 8,
+            _ => return None,
+        };
+        let mut report = Self {
+            src_callsign: src_callsign.into(),
+            pos: Some(pos),
+            ..Default::default()
+        };
+        let compressed = !body
+            .get(offset..)?
+            .starts_with(|c: char| c.is_ascii_digit());
+        let data = if compressed {
+            // Wind direction and speed are stored in compressed course/speed bytes
+            let cs = body.get(offset + 10..offset + 12)?.as_bytes();
+            if cs[0] != b' ' && (b'!'..=b'z').contains(&cs[0]) {
+                report.wind_dir_deg = Some((cs[0] - 33) as f64 * 4.);
+                let knots = 1.08f64.powi((cs[1] as i32) - 33) - 1.;
+                report.wind_speed_mps =
+                    Some(::aprs::MetersPerSecond::from(::aprs::Knots(knots as f32)).as_f64());
+            }
+            body.get(offset + 13..)?
+        } else {
+            let data = body.get(offset + 19..)?;
+            match (data.get(0..3), data.get(3..4), data.get(4..7)) {
+                (Some(dir), Some("/"), Some(speed)) => {
+                    report.wind_dir_deg = parse_value(dir);
+                    report.wind_speed_mps = parse_value(speed).map(mph2mps);
+                    &data[7..]
+                }
+                _ => data,
+            }
+        };
+        report.parse_fields(data);
+        Some(report)
+    }
+
+    fn parse_fields(&mut self, data: &str) {
+        let mut rest = data;
+        while let Some(code) = rest.chars().next() {
+            let width = match code {
+                'c' | 's' | 'g' | 't' | 'r' | 'p' | 'P' | 'L' | 'l' | '#' => 3,
+                'h' => 2,
+                'b' => 5,
+                _ => break, // the rest is a comment or software type
+            };
+            let value = match rest.get(1..1 + width) {
+                Some(v) => parse_value(v),
+                None => break,
+            };
+            match code {
+                'c' => self.wind_dir_deg = value,
+                's' if self.wind_speed_mps.is_none() => self.wind_speed_mps = value.map(mph2mps),
+                'g' => self.wind_gust_mps = value.map(mph2mps),
+                't' => {
+                    self.temperature_c =
+                        value.map(|v| ::aprs::Celsius::from(::aprs::Fahrenheits(v as f32)).as_f64())
+                }
+                'h' => self.humidity_pct = value.map(|v| if v == 0. { 100. } else { v }),
+                'b' => self.pressure_hpa = value.map(|v| v / 10.),
+                _ => (), // rain, luminosity, snow etc.; we don't show those
+            }
+            rest = &rest[1 + width..];
+        }
+    }
+}
+
+/// Parse fixed-width numeric field, dots or spaces mean "no data"
+fn parse_value(v: &str) -> Option<f64> {
+    v.trim().parse::<i32>().ok().map(|v| v as f64)
+}
+
+fn mph2mps(v: f64) -> f64 {
+    ::aprs::MetersPerSecond::from(::aprs::MilesPerHour(v as f32)).as_f64()
+}
@@ -0,0 +1,210 @@
+//! Receiving APRS straight from a radio's audio, e.g.
+//! `arecord -f S16_LE -r 22050 -c 1 -t raw | kiosk --audio -`
+
+use crate::err::{Error, Result};
+use crate::{afsk, ax25, io::aprs::Received};
+use std::io::Read;
+use tokio::sync::mpsc;
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 22050;
+
+/// Lowest sample rate that still has a few samples per 1200 baud bit
+pub const MIN_SAMPLE_RATE: u32 = 8000;
+
+/// Reads 16-bit little endian PCM audio, WAV or raw, from a file, named pipe
+/// or stdin (`-`) until it ends and demodulates APRS packets in it. Sample
+/// rate only matters for raw audio, WAV files say what theirs is.
+pub async fn read(source: String, sample_rate: u32, tx: mpsc::Sender<Received>) -> Result<()> {
+    tokio::task::spawn_blocking(move || {
+        let res = if source == "-" {
+            read_blocking("stdin", std::io::stdin().lock(), sample_rate, tx)
+        } else {
+            let file = std::fs::File::open(&source)
+                .map_err(|e| Error::LoadFile(source.clone().into(), e.to_string()))?;
+            read_blocking(&source, std::io::BufReader::new(file), sample_rate, tx)
+        };
+        if let Err(e) = &res {
+            log::error!("{}: {}", source, e);
+        }
+        res
+    })
+    .await
+    .map_err(|e| Error::Other(e.to_string()))?
+}
+
+fn read_blocking(
+    name: &str,
+    mut input: impl Read,
+    sample_rate: u32,
+    tx: mpsc::Sender<Received>,
+) -> Result<()> {
+    let (format, mut buf) = Format::read(&mut input, sample_rate)?;
+    log::info!(
+        "{}: {} Hz, {} channel(s)",
+        name,
+        format.sample_rate,
+        format.channels
+    );
+    let frame_len = 2 * format.channels as usize;
+    let mut demod = afsk::Demodulator::new(format.sample_rate);
+    let mut chunk = [0u8; 4096];
+
+    loop {
+        let n = input.read(&mut chunk)?;
+        if n == 0 {
+            log::info!("{}: end of audio", name);
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+
+        // Only the first channel if there are more
+        let whole = buf.len() - buf.len() % frame_len;
+        let samples = buf[..whole]
+            .chunks(frame_len)
+            .map(|frame| i16::from_le_bytes([frame[0], frame[1]]))
+            .collect::<Vec<i16>>();
+        buf.drain(..whole);
+
+        for frame in demod.push(&samples) {
+            let line = match ax25::Frame::decode(&frame) {
+                Ok(frame) => frame.to_tnc2(),
+                Err(e) => {
+                    log::debug!("{}: {}", name, e);
+                    continue;
+                }
+            };
+            log::info!("{}: recv {:?}", name, line);
+            // Files decode faster than real time, so wait rather than drop
+            tx.blocking_send(Received::new(name, line))?;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Format {
+    sample_rate: u32,
+    channels: u16,
+}
+
+impl Format {
+    /// Reads WAV header if there is one, returns format and whatever audio
+    /// data has been read along the way
+    fn read(input: &mut impl Read, default_sample_rate: u32) -> Result<(Self, Vec<u8>)> {
+        let mut magic = [0u8; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != b"RIFF" {
+            let raw = Self {
+                sample_rate: default_sample_rate,
+                channels: 1,
+            };
+            return Ok((raw.check()?, magic.to_vec()));
+        }
+        let bad = |why: &str| Error::Other(format!("bad WAV file: {}", why));
+
+        let mut header = [0u8; 8];
+        input.read_exact(&mut header)?;
+        if &header[4..] != b"WAVE" {
+            return Err(bad("not WAVE"));
+        }
+        let mut format = None;
+        loop {
+            input.read_exact(&mut header)?;
+            let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+            match &header[..4] {
+                b"fmt " => {
+                    let mut fmt = vec![0u8; len as usize];
+                    input.read_exact(&mut fmt)?;
+                    if fmt.len() < 16 {
+                        return Err(bad("short fmt chunk"));
+                    }
+                    let tag = u16::from_le_bytes([fmt[0], fmt[1]]);
+                    let bits = u16::from_le_bytes([fmt[14], fmt[15]]);
+                    // PCM or WAVE_FORMAT_EXTENSIBLE
+                    if (tag != 1 && tag != 0xfffe) || bits != 16 {
+                        return Err(bad("only 16-bit PCM is supported"));
+                    }
+                    format = Some(Self {
+                        channels: u16::from_le_bytes([fmt[2], fmt[3]]).max(1),
+                        sample_rate: u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]),
+                    });
+                }
+                // Length is often bogus when recording to a pipe, so just
+                // read till the end
+                b"data" => {
+                    let format = format.ok_or_else(|| bad("no fmt chunk"))?;
+                    return Ok((format.check()?, vec![]));
+                }
+                _ => {
+                    // Chunks are padded to even length
+                    let len = len as u64 + (len as u64 & 1);
+                    std::io::copy(&mut input.take(len), &mut std::io::sink())?;
+                }
+            }
+        }
+    }
+
+    fn check(self) -> Result<Self> {
+        if self.sample_rate < MIN_SAMPLE_RATE {
+            return Err(Error::Other(format!(
+                "sample rate {} Hz is too low, need at least {} Hz",
+                self.sample_rate, MIN_SAMPLE_RATE
+            )));
+        }
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wav(samples: &[i16], sample_rate: u32) -> Vec<u8> {
+        let mut res = vec![];
+        res.extend(b"RIFF");
+        res.extend((36 + samples.len() as u32 * 2).to_le_bytes());
+        res.extend(b"WAVEfmt ");
+        res.extend(16u32.to_le_bytes());
+        res.extend(1u16.to_le_bytes()); // PCM
+        res.extend(1u16.to_le_bytes()); // mono
+        res.extend(sample_rate.to_le_bytes());
+        res.extend((sample_rate * 2).to_le_bytes());
+        res.extend(2u16.to_le_bytes());
+        res.extend(16u16.to_le_bytes());
+        res.extend(b"LIST");
+        res.extend(3u32.to_le_bytes());
+        res.extend(b"abc\0");
+        res.extend(b"data");
+        res.extend((samples.len() as u32 * 2).to_le_bytes());
+        res.extend(samples.iter().flat_map(|v| v.to_le_bytes()));
+        res
+    }
+
+    #[test]
+    fn test_read() {
+        let line = "N0CALL-9>APDW16,WIDE1-1:!4046.40N/11912.12W>Hi";
+        let frame = ax25::Frame::from_tnc2(line).unwrap().encode();
+        let mut samples = afsk::tests::modulate(&frame, 11025);
+        samples.extend([0; 100]);
+
+        let (tx, mut rx) = mpsc::channel(16);
+        let input = std::io::Cursor::new(wav(&samples, 11025));
+        read_blocking("test.wav", input, DEFAULT_SAMPLE_RATE, tx).unwrap();
+        assert_eq!(rx.try_recv(), Ok(Received::new("test.wav", line)));
+
+        // Raw audio has no header, so we need to know the sample rate
+        let (tx, mut rx) = mpsc::channel(16);
+        let raw = samples
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect::<Vec<u8>>();
+        read_blocking("stdin", std::io::Cursor::new(raw.clone()), 11025, tx).unwrap();
+        assert_eq!(rx.try_recv(), Ok(Received::new("stdin", line)));
+
+        // Rates that can't be demodulated are an error, not a crash
+        let (tx, _rx) = mpsc::channel(16);
+        assert!(read_blocking("stdin", std::io::Cursor::new(raw), 0, tx).is_err());
+        let (tx, _rx) = mpsc::channel(16);
+        let input = std::io::Cursor::new(wav(&samples, 0));
+        assert!(read_blocking("test.wav", input, DEFAULT_SAMPLE_RATE, tx).is_err());
+    }
+}
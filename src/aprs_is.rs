@@ -1,23 +1,128 @@
 use crate::err::{Error, Result};
-use std::time::Duration;
+use crate::io::aprs::Received;
+use crate::util::{geo::Point, sync::recv_if_some};
+use std::time::{Duration, Instant};
 use tokio::{
     io::{AsyncBufReadExt, AsyncWriteExt},
     sync::mpsc,
 };
 
 pub static DEFAULT_SERVER: &str = "rotate.aprs2.net:14580";
+pub static DEFAULT_CALLSIGN: &str = "N0CALL";
+pub const DEFAULT_RADIUS_KM: f64 = 200.;
 
-pub async fn read(server: impl AsRef<str>, tx: mpsc::Sender<String>) -> Result<()> {
-    let server = server.as_ref();
-    loop {
-        if let Err(e) = try_read(&server, tx.clone()).await {
+const MIN_BACKOFF: Duration = Duration::from_secs(3);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+/// Sessions that lasted at least this long reset the backoff
+const STABLE_SESSION: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Servers to try in turn, e.g. `rotate.aprs2.net:14580`
+    pub servers: Vec<String>,
+    pub callsign: String,
+    /// Center and radius of the server-side range filter
+    pub center: Point,
+    pub radius_km: f64,
+    /// Stations we want to hear wherever they are (budlist filter)
+    pub buddies: Vec<String>,
+    /// Callsign prefixes we want to hear wherever they are (prefix filter)
+    pub prefixes: Vec<String>,
+}
+
+impl Config {
+    pub fn new(servers: Vec<String>, callsign: impl Into<String>, center: Point) -> Self {
+        Self {
+            servers,
+            callsign: callsign.into(),
+            center,
+            radius_km: DEFAULT_RADIUS_KM,
+            buddies: vec![],
+            prefixes: vec![],
+        }
+    }
+
+    /// Server-side filter, see https://www.aprs-is.net/javAPRSFilter.aspx
+    pub fn filter(&self) -> String {
+        let mut res = format!(
+            "r/{:.4}/{:.4}/{}",
+            self.center.lat(),
+            self.center.lng(),
+            self.radius_km.round()
+        );
+        if !self.buddies.is_empty() {
+            res.push_str(" b/");
+            res.push_str(&self.buddies.join("/"));
+        }
+        if !self.prefixes.is_empty() {
+            res.push_str(" p/");
+            res.push_str(&self.prefixes.join("/"));
+        }
+        res
+    }
+
+    pub fn login(&self) -> String {
+        // Default callsign is only good for receiving, don't pretend otherwise
+        let pass = if self.callsign.eq_ignore_ascii_case(DEFAULT_CALLSIGN) {
+            -1
+        } else {
+            passcode(&self.callsign) as i32
+        };
+        format!(
+            "user {} pass {} vers liveplaya {} filter {}\r\n",
+            self.callsign,
+            pass,
+            env!("CARGO_PKG_VERSION"),
+            self.filter()
+        )
+    }
+}
+
+/// APRS-IS passcode, a well-known hash of the callsign without SSID
+pub fn passcode(callsign: &str) -> u16 {
+    let call = callsign.split('-').next().unwrap_or_default();
+    let mut hash: u16 = 0x73e2;
+    for pair in call.to_ascii_uppercase().as_bytes().chunks(2) {
+        hash ^= (pair[0] as u16) << 8;
+        if let Some(&b) = pair.get(1) {
+            hash ^= b as u16;
+        }
+    }
+    hash & 0x7fff
+}
+
+/// Reads packets from APRS-IS and, if `uplink` is given, sends packets that
+/// come from it to APRS-IS (e.g. the ones we gate from RF)
+pub async fn read(
+    config: Config,
+    mut uplink: Option<mpsc::Receiver<String>>,
+    tx: mpsc::Sender<Received>,
+) -> Result<()> {
+    if config.servers.is_empty() {
+        return Err(Error::MissingRequiredArgument("APRS-IS server".into()));
+    }
+    let mut backoff = MIN_BACKOFF;
+    for server in config.servers.iter().cycle() {
+        let started = Instant::now();
+        if let Err(e) = try_read(server, &config, uplink.as_mut(), tx.clone()).await {
             log::error!("{}: {}", server, e);
         }
-        tokio::time::sleep(Duration::from_secs(3)).await;
+        if started.elapsed() >= STABLE_SESSION {
+            backoff = MIN_BACKOFF;
+        }
+        log::debug!("{}: reconnecting in {}s", server, backoff.as_secs());
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
     }
+    unreachable!()
 }
 
-async fn try_read(server: &str, tx: mpsc::Sender<String>) -> Result<()> {
+async fn try_read(
+    server: &str,
+    config: &Config,
+    mut uplink: Option<&mut mpsc::Receiver<String>>,
+    tx: mpsc::Sender<Received>,
+) -> Result<()> {
     let timeout = std::time::Duration::from_secs(5);
 
     log::debug!("{}: connecting...", server);
@@ -25,19 +130,30 @@ async fn try_read(server: &str, tx: mpsc::Sender<String>) -> Result<()> {
         tokio::time::timeout(timeout, tokio::net::TcpStream::connect(server)).await??;
     log::info!("{}: connected", server);
 
-    let hello = b"user N0CALL pass -1 vers liveplaya 0.00 filter r/40.79608429483125/-119.19589964220306/200\r\n";
-    // let hello = b"user N0CALL pass -1 vers liveplaya 0.00 filter r/37.371111/-122.0375/100 r/40.79608429483125/-119.19589964220306/100\r\n";
+    let hello = config.login();
 
     log::debug!("{}: starting session...", server);
-    tokio::time::timeout(timeout, stream.write_all(hello)).await??;
+    tokio::time::timeout(timeout, stream.write_all(hello.as_bytes())).await??;
 
-    let (_stream_rx, _stream_tx) = stream.split();
-    let mut lines = tokio::io::BufReader::new(stream).lines();
+    let (stream_rx, mut stream_tx) = stream.into_split();
+    let mut lines = tokio::io::BufReader::new(stream_rx).lines();
 
     // read packets
-    let timeout = std::time::Duration::from_secs(180);
- 
-    while let Some(line) = tokio::time::timeout(timeout, lines.next_line()).await?? {
+    let read_timeout = std::time::Duration::from_secs(180);
+
+    loop {
+        let line = tokio::select! {
+            line = tokio::time::timeout(read_timeout, lines.next_line()) => match line?? {
+                Some(line) => line,
+                None => break,
+            },
+            Some(line) = recv_if_some(&mut uplink) => {
+                log::info!("{}: send {:?}", server, line);
+                let data = format!("{}\r\n", line);
+                tokio::time::timeout(timeout, stream_tx.write_all(data.as_bytes())).await??;
+                continue;
+            }
+        };
         if line.chars().all(|c| c.is_whitespace()) {
             continue; // skip whitespace
         }
@@ -45,11 +161,14 @@ async fn try_read(server: &str, tx: mpsc::Sender<String>) -> Result<()> {
         if line.starts_with("# Login by user not allowed") {
             return Err(Error::msg("can't login"));
         }
+        if line.starts_with("# logresp") && line.contains(" unverified") && uplink.is_some() {
+            log::warn!("{}: login unverified, server will drop our packets", server);
+        }
         if line.starts_with('#') {
             continue;
         }
 
-        match tx.try_send(line) {
+        match tx.try_send(Received::new(server, line)) {
             Ok(()) => (),
             Err(_) => {
                 log::error!("busy, dropping packet");
@@ -58,3 +177,70 @@ async fn try_read(server: &str, tx: mpsc::Sender<String>) -> Result<()> {
     }
     Err(Error::Disconnected)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_login() {
+        assert_eq!(passcode("N0CALL"), 13023);
+        assert_eq!(passcode("k6cqu-5"), passcode("K6CQU"));
+
+        let mut config = Config::new(
+            vec![DEFAULT_SERVER.into()],
+            "K6CQU-5",
+            Point::unchecked(-119.2035, 40.7864),
+        );
+        config.buddies = vec!["TGECKO".into(), "DUCK".into()];
+        config.prefixes = vec!["DF".into()];
+        assert_eq!(
+            config.login(),
+            format!(
+                "user K6CQU-5 pass 11909 vers liveplaya {} \
+                 filter r/40.7864/-119.2035/200 b/TGECKO/DUCK p/DF\r\n",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_uplink() {
+        use tokio::io::AsyncReadExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        let fake_aprsis = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream
+                .write_all(b"# aprsc 2.1.14\r\nDUCK>APRS,TCPIP*,qAC,T2TEST:>hi\r\n")
+                .await
+                .unwrap();
+            let mut received = String::new();
+            while !received.ends_with("qAR,K6CQU-5:>hello\r\n") {
+                let mut buf = [0u8; 256];
+                let n = stream.read(&mut buf).await.unwrap();
+                assert!(n > 0, "unexpected EOF, got {:?}", received);
+                received.push_str(std::str::from_utf8(&buf[..n]).unwrap());
+            }
+            received
+        });
+
+        let config = Config::new(vec![server.clone()], "K6CQU-5", Point::BRD_CENTER);
+        let (uplink_tx, mut uplink_rx) = mpsc::channel(16);
+        let (tx, mut rx) = mpsc::channel(16);
+        uplink_tx
+            .send("TGECKO>APRS,WIDE1-1,qAR,K6CQU-5:>hello".into())
+            .await
+            .unwrap();
+        let res = try_read(&server, &config, Some(&mut uplink_rx), tx).await;
+        assert!(res.is_err(), "should end with disconnect");
+
+        let received = fake_aprsis.await.unwrap();
+        assert!(received.starts_with("user K6CQU-5 pass 11909 "));
+        assert_eq!(
+            rx.recv().await,
+            Some(Received::new(&server, "DUCK>APRS,TCPIP*,qAC,T2TEST:>hi"))
+        );
+    }
+}
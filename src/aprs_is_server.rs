@@ -0,0 +1,325 @@
+//! Minimal APRS-IS server, so that APRS clients on the local network (Xastir,
+//! YAAC, APRSdroid...) can use the kiosk as their server with no Internet.
+//! See http://www.aprs-is.net/Connecting.aspx
+
+use crate::aprs::Packet;
+use crate::aprs_is::passcode;
+use crate::err::{Error, Result};
+use crate::util::geo::Point;
+use std::time::Duration;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::broadcast,
+};
+
+/// Number of packets a slow client can fall behind before it starts missing
+/// some
+pub const FEED_CAPACITY: usize = 1024;
+
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(30);
+const WRITE_TIMEOUT: Duration = Duration::from_secs(30);
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(20);
+/// How long to wait before accepting again after it failed, e.g. because
+/// we've run out of file descriptors
+const ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Server-side filter, see https://www.aprs-is.net/javAPRSFilter.aspx; only
+/// range, budlist and prefix filters are supported
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filter {
+    ranges: Vec<(Point, f64)>,
+    buddies: Vec<String>,
+    prefixes: Vec<String>,
+}
+
+impl Filter {
+    /// Parses filter, e.g. `r/40.78/-119.20/50 b/TGECKO/DF* p/K6`, ignoring
+    /// the parts we don't understand
+    pub fn parse(s: &str) -> Self {
+        let mut res = Self::default();
+        for part in s.split_whitespace() {
+            let mut args = part.split('/');
+            match args.next().unwrap_or_default() {
+                "r" => {
+                    let args = args
+                        .map(|v| v.parse::<f64>().ok())
+                        .collect::<Option<Vec<_>>>();
+                    match args.as_deref() {
+                        Some(&[lat, lng, km]) => match Point::from_latlng((lat, lng)) {
+                            Ok(pt) => res.ranges.push((pt, km)),
+                            Err(_) => log::debug!("bad range filter {:?}", part),
+                        },
+                        _ => log::debug!("bad range filter {:?}", part),
+                    }
+                }
+                "b" => res.buddies.extend(args.map(|v| v.to_ascii_uppercase())),
+                "p" => res.prefixes.extend(args.map(|v| v.to_ascii_uppercase())),
+                _ => log::debug!("unsupported filter {:?}", part),
+            }
+        }
+        res
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty() && self.buddies.is_empty() && self.prefixes.is_empty()
+    }
+
+    /// Returns true if the packet passes any of the filters
+    pub fn matches(&self, line: &str) -> bool {
+        let src = line.split('>').next().unwrap_or_default();
+        if self.buddies.iter().any(|b| match b.strip_suffix('*') {
+            Some(prefix) => src.starts_with(prefix),
+            None => src == b,
+        }) {
+            return true;
+        }
+        if self.prefixes.iter().any(|p| src.starts_with(p.as_str())) {
+            return true;
+        }
+        if self.ranges.is_empty() {
+            return false;
+        }
+        match location(line) {
+            Some(pt) => self
+                .ranges
+                .iter()
+                .any(|(center, km)| pt.haversine_distance_m(*center) <= km * 1000.),
+            None => false,
+        }
+    }
+}
+
+fn location(line: &str) -> Option<Point> {
+    match Packet::parse(line).ok()? {
+        Packet::Position(report) => Some(report.pos.location),
+        Packet::Object(report) => Some(report.pos.location),
+        Packet::Weather(report) => report.pos.map(|pos| pos.location),
+        Packet::Message(_) | Packet::Bulletin(_) => None,
+    }
+}
+
+/// Returns addressee if the packet is a message
+fn addressee(line: &str) -> Option<&str> {
+    let (_, info) = line.split_once(':')?;
+    let addressee = info.strip_prefix(':')?.get(..9)?;
+    Some(addressee.trim_end())
+}
+
+/// Login line, e.g. `user K6CQU-7 pass 12345 vers YAAC 1.0 filter r/40.7/-119.2/50`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Login {
+    pub callsign: String,
+    pub verified: bool,
+    pub software: String,
+    pub filter: Filter,
+}
+
+impl Login {
+    pub fn parse(line: &str) -> Result<Self> {
+        let bad = || Error::BadRequest(format!("bad login {:?}", line));
+        let (line, filter) = match line.split_once(" filter ") {
+            Some((line, filter)) => (line, Filter::parse(filter)),
+            None => (line, Filter::default()),
+        };
+        let mut words = line.split_whitespace();
+        if words.next() != Some("user") {
+            return Err(bad());
+        }
+        let callsign = words.next().ok_or_else(bad)?.to_ascii_uppercase();
+        let mut verified = false;
+        let mut software = vec![];
+        while let Some(word) = words.next() {
+            match word {
+                "pass" => {
+                    let pass = words.next().and_then(|v| v.parse::<i32>().ok());
+                    verified = pass == Some(passcode(&callsign) as i32);
+                }
+                "vers" => software.extend(words.by_ref()),
+                _ => (),
+            }
+        }
+        Ok(Self {
+            callsign,
+            verified,
+            software: software.join(" "),
+            filter,
+        })
+    }
+}
+
+/// Accepts clients on `port` and streams packets from `feed` to them
+pub async fn serve(port: u16, server_name: String, feed: broadcast::Sender<String>) -> Result<()> {
+    let listener = TcpListener::bind(("0.0.0.0", port)).await?;
+    log::info!("APRS-IS server listening on port {}", port);
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("aprsis: can't accept, {}", e);
+                tokio::time::sleep(ACCEPT_BACKOFF).await;
+                continue;
+            }
+        };
+        let server_name = server_name.clone();
+        let rx = feed.subscribe();
+        tokio::spawn(async move {
+            log::info!("aprsis {}: connected", addr);
+            if let Err(e) = serve_client(stream, &server_name, rx).await {
+                log::info!("aprsis {}: {}", addr, e);
+            }
+        });
+    }
+}
+
+async fn serve_client(
+    stream: TcpStream,
+    server_name: &str,
+    mut feed: broadcast::Receiver<String>,
+) -> Result<()> {
+    let (stream_rx, mut stream_tx) = stream.into_split();
+    let mut lines = tokio::io::BufReader::new(stream_rx).lines();
+    let banner = format!("# liveplaya {}\r\n", env!("CARGO_PKG_VERSION"));
+    stream_tx.write_all(banner.as_bytes()).await?;
+
+    let login = match tokio::time::timeout(LOGIN_TIMEOUT, lines.next_line()).await?? {
+        Some(line) => Login::parse(&line)?,
+        None => return Err(Error::Disconnected),
+    };
+    log::info!(
+        "aprsis: {} logged in ({}), filter {:?}",
+        login.callsign,
+        login.software,
+        login.filter
+    );
+    let logresp = format!(
+        "# logresp {} {}, server {}\r\n",
+        login.callsign,
+        if login.verified {
+            "verified"
+        } else {
+            "unverified"
+        },
+        server_name
+    );
+    stream_tx.write_all(logresp.as_bytes()).await?;
+
+    let mut filter = login.filter;
+    let mut keepalive = tokio::time::interval(KEEPALIVE_INTERVAL);
+    loop {
+        let data = tokio::select! {
+            line = lines.next_line() => {
+                let line = line?.ok_or(Error::Disconnected)?;
+                // Filter can be changed mid-session with a `#filter` command
+                if let Some(v) = line.strip_prefix("#filter ") {
+                    filter = Filter::parse(v);
+                    log::info!("aprsis: {} set filter {:?}", login.callsign, filter);
+                } else if !line.starts_with('#') && !line.trim().is_empty() {
+                    log::debug!("aprsis: {} sent {:?}, ignoring", login.callsign, line);
+                }
+                continue;
+            }
+            _ = keepalive.tick() => format!(
+                "# liveplaya {} {} {}\r\n",
+                env!("CARGO_PKG_VERSION"),
+                crate::util::time::Timestamp::now(),
+                server_name
+            ),
+            line = feed.recv() => match line {
+                Ok(line) => {
+                    let to_us = addressee(&line)
+                        .map(|v| v.eq_ignore_ascii_case(&login.callsign))
+                        .unwrap_or_default();
+                    if !(filter.is_empty() || to_us || filter.matches(&line)) {
+                        continue;
+                    }
+                    format!("{}\r\n", line)
+                }
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    log::warn!("aprsis: {} is too slow, skipped {} packets", login.callsign, n);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            },
+        };
+        tokio::time::timeout(WRITE_TIMEOUT, stream_tx.write_all(data.as_bytes())).await??;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter() {
+        let filter = Filter::parse("r/40.7864/-119.2065/10 b/TGECKO/DF* p/K6 x/whatever");
+        assert!(filter.matches("TGECKO>APRS:>hi"));
+        assert!(filter.matches("DFGUPY>APRS:>hi"));
+        assert!(filter.matches("K6CQU-5>APRS:>hi"));
+        assert!(filter.matches("DUCK>APRS:!4046.40N/11912.12W>"));
+        assert!(!filter.matches("DUCK>APRS:!3746.40N/12212.12W>"));
+        assert!(!filter.matches("DUCK>APRS:>hi"));
+        assert!(Filter::parse("").is_empty());
+    }
+
+    #[test]
+    fn test_login() {
+        let line = format!(
+            "user K6CQU-7 pass {} vers YAAC 1.0 filter b/TGECKO",
+            passcode("K6CQU")
+        );
+        let login = Login::parse(&line).unwrap();
+        assert_eq!(login.callsign, "K6CQU-7");
+        assert_eq!(login.software, "YAAC 1.0");
+        assert!(login.verified);
+        assert!(login.filter.matches("TGECKO>APRS:>hi"));
+
+        let login = Login::parse("user N0CALL pass -1 vers aprsc 2.1").unwrap();
+        assert!(!login.verified);
+        assert!(login.filter.is_empty());
+        assert!(Login::parse("GET / HTTP/1.1").is_err());
+    }
+
+    async fn next_packet(
+        lines: &mut tokio::io::Lines<tokio::io::BufReader<tokio::net::tcp::OwnedReadHalf>>,
+    ) -> String {
+        loop {
+            let line = lines.next_line().await.unwrap().unwrap();
+            if !line.starts_with('#') {
+                return line;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_serve() {
+        let (feed, _) = broadcast::channel(16);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let rx = feed.subscribe();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            serve_client(stream, "K6CQU-5", rx).await
+        });
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (stream_rx, mut stream_tx) = stream.into_split();
+        let mut lines = tokio::io::BufReader::new(stream_rx).lines();
+        stream_tx
+            .write_all(b"user N0CALL pass -1 vers test 1 filter p/TG\r\n")
+            .await
+            .unwrap();
+        // Login is through once the server says so
+        loop {
+            let line = lines.next_line().await.unwrap().unwrap();
+            if line.starts_with("# logresp") {
+                break;
+            }
+        }
+        feed.send("DUCK>APRS:>quack".into()).unwrap();
+        feed.send("K6CQU>APRS::N0CALL   :hi{1".into()).unwrap();
+        feed.send("TGECKO>APRS:>hi".into()).unwrap();
+        assert_eq!(next_packet(&mut lines).await, "K6CQU>APRS::N0CALL   :hi{1");
+        assert_eq!(next_packet(&mut lines).await, "TGECKO>APRS:>hi");
+    }
+}
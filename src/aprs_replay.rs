@@ -0,0 +1,88 @@
+//! Playing back a recorded event log as if the packets were heard now, to
+//! demo or test the kiosk when there's nothing on the air
+
+use crate::err::{Error, Result};
+use crate::{
+    io::{aprs::Received, store::Record},
+    svc::jsonlog::JsonLog,
+    util::time::{Duration, Timespan},
+};
+use std::path::PathBuf;
+use tokio::sync::mpsc;
+
+/// Sends packets from the event log at `path` in the order they were
+/// recorded, `speed` times faster than they came in; pauses are cut to
+/// `max_pause` though, so that a replay doesn't sit through the night when
+/// nobody was around
+pub async fn read(
+    path: PathBuf,
+    speed: f64,
+    max_pause: Duration,
+    tx: mpsc::Sender<Received>,
+) -> Result<()> {
+    if !(speed.is_finite() && speed > 0.) {
+        return Err(Error::Other(format!("bad replay speed {}", speed)));
+    }
+    let name = path.to_string_lossy().to_string();
+    let mut records = JsonLog::<Record>::new(path).records(Timespan::MAX).await?;
+    log::info!("{}: replaying at {}x", name, speed);
+
+    let mut prev_ts = None;
+    while let Some((ts, rec)) = records.next().await? {
+        let data = match rec {
+            Record::AprsPacket { data, .. } => data,
+        };
+        if let Some(prev_ts) = prev_ts {
+            // Log is in the order packets came in even if the clock wasn't
+            let pause = match ts > prev_ts {
+                true => ts.duration_between(prev_ts).div_f64(speed),
+                false => Duration::ZERO,
+            };
+            if pause > max_pause {
+                log::info!("{}: skipping {:?} of silence", name, pause - max_pause);
+            }
+            tokio::time::sleep(pause.min(max_pause)).await;
+        }
+        prev_ts = Some(ts);
+        tx.send(Received::new(&name, data)).await?;
+    }
+    log::info!("{}: end of replay", name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::time::Timestamp;
+
+    #[tokio::test]
+    async fn test_replay() {
+        let path = std::env::temp_dir().join(format!("lpkiosk-replay-{}.log", std::process::id()));
+        let mut log = JsonLog::new(&path);
+        let t0 = Timestamp::from_calendar_utc(2023, 8, 30, 21, 0, 0).unwrap();
+        for (secs, data) in [(0, "DUCK>APRS:>one"), (2, "DUCK>APRS:>two")] {
+            let rec = Record::AprsPacket {
+                data: data.into(),
+                source: Some("/dev/ttyUSB0".into()),
+                path: vec![],
+                digipeater: None,
+            };
+            let ts = t0.add(Duration::from_secs(secs)).unwrap();
+            log.write(ts, &rec).await.unwrap();
+        }
+
+        let (tx, mut rx) = mpsc::channel(16);
+        let started = std::time::Instant::now();
+        read(path.clone(), 10., Duration::from_secs(60), tx)
+            .await
+            .unwrap();
+        let elapsed = started.elapsed();
+        std::fs::remove_file(&path).ok();
+
+        let name = path.to_string_lossy().to_string();
+        assert_eq!(rx.try_recv(), Ok(Received::new(&name, "DUCK>APRS:>one")));
+        assert_eq!(rx.try_recv(), Ok(Received::new(&name, "DUCK>APRS:>two")));
+        assert!(elapsed >= Duration::from_millis(200), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(2), "{:?}", elapsed);
+    }
+}
@@ -0,0 +1,365 @@
+//! Made-up traffic: art cars, bikes and people wandering the city streets
+//! and beaconing like real trackers do, for load testing, demos and frontend
+//! work when there's nobody on the playa
+
+use crate::{
+    aprs::Symbol,
+    beacon,
+    brc::BlackRockCity,
+    err::Result,
+    io::aprs::Received,
+    util::{geo::Point, time::Timestamp},
+};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+pub static SOURCE: &str = "sim";
+
+const TICK: Duration = Duration::from_secs(1);
+const KNOTS_IN_MPS: f64 = 1.94384;
+
+/// Streets closer than this are the same street, data is only so precise
+const STREET_TOLERANCE_M: f64 = 1.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    ArtCar,
+    Bike,
+    Walker,
+}
+
+impl Kind {
+    fn speed_mps(&self) -> f64 {
+        match self {
+            Kind::ArtCar => 2.2, // 5 mph is the limit
+            Kind::Bike => 4.,
+            Kind::Walker => 1.3,
+        }
+    }
+
+    fn beacon_interval_s(&self) -> f64 {
+        match self {
+            Kind::ArtCar => 30.,
+            Kind::Bike => 60.,
+            Kind::Walker => 120.,
+        }
+    }
+
+    fn ssid(&self) -> u8 {
+        match self {
+            Kind::ArtCar => 9,
+            Kind::Bike => 12,
+            Kind::Walker => 7,
+        }
+    }
+
+    fn symbol(&self) -> Symbol {
+        match self {
+            Kind::ArtCar => Symbol::new('/', '>'),
+            Kind::Bike => Symbol::new('/', 'b'),
+            Kind::Walker => Symbol::new('/', '['),
+        }
+    }
+}
+
+/// Place in the city as clock angle (degrees, 0 is 12:00) and distance
+/// from the Man
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Polar {
+    deg: f64,
+    radius_m: f64,
+}
+
+#[derive(Debug)]
+struct Agent {
+    callsign: String,
+    kind: Kind,
+    pos: Polar,
+    /// Intersection we're heading to
+    target: Polar,
+    /// Intersection we came from, so that we don't turn right back
+    prev: Polar,
+    next_beacon_s: f64,
+}
+
+/// Tiny xorshift generator, the simulation doesn't need anything better
+#[derive(Debug)]
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Street grid and everybody on it
+pub struct Sim {
+    city: BlackRockCity,
+    /// Ring radii, innermost first
+    rings: Vec<f64>,
+    /// Radial clock angles and how far from the Man they go
+    radials: Vec<(f64, f64, f64)>,
+    agents: Vec<Agent>,
+    rng: Rng,
+    elapsed_s: f64,
+}
+
+impl Sim {
+    pub fn new(city: BlackRockCity, count: usize, seed: u64) -> Self {
+        let mut rings = city.rings().map(|r| r.radius_m()).collect::<Vec<_>>();
+        rings.sort_by(f64::total_cmp);
+        let mut radials = city
+            .radials()
+            .map(|r| {
+                let deg = r.direction().to_degrees();
+                (deg, r.inner_radius_m(), r.outer_radius_m())
+            })
+            .collect::<Vec<_>>();
+        radials.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut sim = Self {
+            city,
+            rings,
+            radials,
+            agents: vec![],
+            rng: Rng(seed | 1),
+            elapsed_s: 0.,
+        };
+
+        let crossings = sim.crossings();
+        for i in 0..count {
+            let kind = match i % 4 {
+                0 => Kind::ArtCar,
+                1 | 2 => Kind::Bike,
+                _ => Kind::Walker,
+            };
+            let pos = crossings[sim.rng.below(crossings.len())];
+            let next_beacon_s = sim.rng.unit() * kind.beacon_interval_s();
+            let target = sim.next_crossing(pos, pos);
+            sim.agents.push(Agent {
+                callsign: format!("SIM{}-{}", i + 1, kind.ssid()),
+                kind,
+                pos,
+                target,
+                prev: pos,
+                next_beacon_s,
+            });
+        }
+        sim
+    }
+
+    /// Moves everybody along and returns position packets of those due to
+    /// beacon
+    pub fn step(&mut self, dt: Duration) -> Vec<String> {
+        self.elapsed_s += dt.as_secs_f64();
+        let mut res = vec![];
+        for i in 0..self.agents.len() {
+            let from = self.location(self.agents[i].pos);
+            let mut left_m = self.agents[i].kind.speed_mps() * dt.as_secs_f64();
+            while left_m > 0. {
+                let agent = &mut self.agents[i];
+                let (target, prev) = (agent.target, agent.prev);
+                left_m = advance(&mut agent.pos, target, left_m);
+                if agent.pos != target {
+                    break;
+                }
+                let next = self.next_crossing(target, prev);
+                if next == target {
+                    break; // dead end with nowhere to turn, shouldn't happen
+                }
+                let agent = &mut self.agents[i];
+                agent.prev = target;
+                agent.target = next;
+            }
+
+            let agent = &self.agents[i];
+            if self.elapsed_s < agent.next_beacon_s {
+                continue;
+            }
+            let to = self.location(agent.pos);
+            let course = match from.haversine_bearing_deg(to).round() as i32 {
+                deg if deg <= 0 => deg + 360,
+                deg => deg,
+            };
+            let knots = (agent.kind.speed_mps() * KNOTS_IN_MPS).round() as u32;
+            let info = format!(
+                "!{}{:03}/{:03}",
+                beacon::format_location(to, agent.kind.symbol()),
+                course,
+                knots
+            );
+            res.push(beacon::packet(&agent.callsign, "", &info));
+            let interval_s = agent.kind.beacon_interval_s();
+            self.agents[i].next_beacon_s += interval_s;
+        }
+        res
+    }
+
+    fn location(&self, pos: Polar) -> Point {
+        self.city
+            .center()
+            .haversine_destination(pos.deg + self.city.bearing_deg(), pos.radius_m)
+    }
+
+    /// Every place where a ring and a radial meet
+    fn crossings(&self) -> Vec<Polar> {
+        let mut res = vec![];
+        for &(deg, inner_m, outer_m) in self.radials.iter() {
+            for &radius_m in self.rings.iter() {
+                if is_on_radial(radius_m, inner_m, outer_m) {
+                    res.push(Polar { deg, radius_m });
+                }
+            }
+        }
+        res
+    }
+
+    /// Picks one of the neighboring intersections, preferably not the one
+    /// we've just come from
+    fn next_crossing(&mut self, at: Polar, prev: Polar) -> Polar {
+        let mut options = vec![];
+        // Along the ring
+        let on_ring = self
+            .radials
+            .iter()
+            .filter(|(_, inner_m, outer_m)| is_on_radial(at.radius_m, *inner_m, *outer_m))
+            .map(|(deg, _, _)| *deg)
+            .collect::<Vec<_>>();
+        if let Some(idx) = on_ring.iter().position(|deg| (deg - at.deg).abs() < 1e-6) {
+            let neighbors = [idx.checked_sub(1), Some(idx + 1)];
+            for deg in neighbors
+                .into_iter()
+                .flatten()
+                .filter_map(|i| on_ring.get(i))
+            {
+                options.push(Polar { deg: *deg, ..at });
+            }
+        }
+        // Along the radial
+        if let Some((_, inner_m, outer_m)) = self
+            .radials
+            .iter()
+            .find(|(deg, _, _)| (deg - at.deg).abs() < 1e-6)
+        {
+            let on_radial = self
+                .rings
+                .iter()
+                .copied()
+                .filter(|radius_m| is_on_radial(*radius_m, *inner_m, *outer_m))
+                .collect::<Vec<_>>();
+            if let Some(idx) = on_radial
+                .iter()
+                .position(|r| (r - at.radius_m).abs() < 1e-6)
+            {
+                let neighbors = [idx.checked_sub(1), Some(idx + 1)];
+                for radius_m in neighbors
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|i| on_radial.get(i))
+                {
+                    options.push(Polar {
+                        radius_m: *radius_m,
+                        ..at
+                    });
+                }
+            }
+        }
+        if options.len() > 1 {
+            options.retain(|v| *v != prev);
+        }
+        match options.len() {
+            0 => at,
+            n => options[self.rng.below(n)],
+        }
+    }
+}
+
+fn is_on_radial(radius_m: f64, inner_m: f64, outer_m: f64) -> bool {
+    radius_m >= inner_m - STREET_TOLERANCE_M && radius_m <= outer_m + STREET_TOLERANCE_M
+}
+
+/// Moves `pos` up to `dist_m` towards `target` along the street they share,
+/// returns how much of the distance is left after getting there
+fn advance(pos: &mut Polar, target: Polar, dist_m: f64) -> f64 {
+    if pos.deg == target.deg {
+        let to_go_m = target.radius_m - pos.radius_m;
+        if to_go_m.abs() <= dist_m {
+            *pos = target;
+            return dist_m - to_go_m.abs();
+        }
+        pos.radius_m += dist_m * to_go_m.signum();
+    } else {
+        let to_go_deg = target.deg - pos.deg;
+        let dist_deg = (dist_m / pos.radius_m).to_degrees();
+        if to_go_deg.abs() <= dist_deg {
+            *pos = target;
+            return (dist_deg - to_go_deg.abs()).to_radians() * pos.radius_m;
+        }
+        pos.deg += dist_deg * to_go_deg.signum();
+    }
+    0.
+}
+
+/// Runs simulation in real time, sending packets of `count` made-up stations
+pub async fn read(city: BlackRockCity, count: usize, tx: mpsc::Sender<Received>) -> Result<()> {
+    let seed = Timestamp::now().as_unix_millis();
+    let mut sim = Sim::new(city, count, seed);
+    log::info!("simulating {} stations", count);
+    let mut tick = tokio::time::interval(TICK);
+    loop {
+        tick.tick().await;
+        for line in sim.step(TICK) {
+            tx.send(Received::new(SOURCE, line)).await?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aprs::Packet;
+
+    #[test]
+    fn test_sim() {
+        let city = crate::brc2023::get();
+        let center = city.center();
+        let max_m = city.last_ring().radius_m() + STREET_TOLERANCE_M;
+        let mut sim = Sim::new(city, 20, 42);
+
+        let mut first = std::collections::HashMap::new();
+        let mut last = std::collections::HashMap::new();
+        for _ in 0..600 {
+            for line in sim.step(TICK) {
+                let report = match Packet::parse(&line) {
+                    Ok(Packet::Position(report)) => report,
+                    v => panic!("{}: {:?}", line, v),
+                };
+                let dist_m = report.pos.location.haversine_distance_m(center);
+                assert!(dist_m < max_m + 10., "{} is off the grid", line);
+                first
+                    .entry(report.src_callsign.clone())
+                    .or_insert(report.pos.location);
+                last.insert(report.src_callsign, report.pos.location);
+            }
+        }
+        // Everybody has beaconed a few times and got somewhere
+        assert_eq!(last.len(), 20);
+        for (call, pt) in last {
+            assert!(
+                first[&call].haversine_distance_m(pt) > 10.,
+                "{} is stuck",
+                call
+            );
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use crate::err::{Error, Result};
+use crate::{agwpe, aprs_tty, ax25, io::aprs::Received};
+use std::time::Duration;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    sync::mpsc,
+};
+
+pub static DEFAULT_KISS_SERVER: &str = "localhost:8001";
+pub static DEFAULT_AGWPE_SERVER: &str = "localhost:8000";
+
+/// Software TNC protocol, e.g. Dire Wolf serves both
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Kiss,
+    Agwpe,
+}
+
+pub async fn read(
+    server: impl AsRef<str>,
+    protocol: Protocol,
+    mut outgoing: Option<mpsc::Receiver<String>>,
+    tx: mpsc::Sender<Received>,
+) -> Result<()> {
+    let server = server.as_ref();
+    loop {
+        if let Err(e) = try_read(server, protocol, outgoing.as_mut(), tx.clone()).await {
+            log::error!("{}: {}", server, e);
+        }
+        tokio::time::sleep(Duration::from_secs(3)).await;
+    }
+}
+
+/// Only KISS connections transmit `outgoing` packets
+async fn try_read(
+    server: &str,
+    protocol: Protocol,
+    outgoing: Option<&mut mpsc::Receiver<String>>,
+    tx: mpsc::Sender<Received>,
+) -> Result<()> {
+    let timeout = Duration::from_secs(5);
+
+    log::debug!("{}: connecting...", server);
+    let mut stream = tokio::time::timeout(timeout, TcpStream::connect(server)).await??;
+    log::info!("{}: connected ({:?})", server, protocol);
+
+    // RF can be quiet for a long while and the TNC is usually on the same
+    // box, so we don't need to be eager about detecting dead connections
+    let timeout = Duration::from_secs(900);
+
+    match protocol {
+        Protocol::Kiss => aprs_tty::read_kiss(server, stream, timeout, outgoing, tx).await,
+        Protocol::Agwpe => {
+            let hello = agwpe::Header::new(0, agwpe::KIND_RAW_ON).encode();
+            stream.write_all(&hello).await?;
+            read_agwpe(server, stream, timeout, tx).await
+        }
+    }
+}
+
+async fn read_agwpe(
+    server: &str,
+    mut stream: TcpStream,
+    timeout: Duration,
+    tx: mpsc::Sender<Received>,
+) -> Result<()> {
+    const MAX_DATA_LEN: usize = 64 * 1024;
+    let mut buf = [0u8; agwpe::HEADER_LEN];
+
+    loop {
+        tokio::time::timeout(timeout, stream.read_exact(&mut buf)).await??;
+        let header = agwpe::Header::decode(&buf);
+        let data_len = header.data_len as usize;
+        if data_len > MAX_DATA_LEN {
+            return Err(Error::Other(format!("bad AGWPE frame length {}", data_len)));
+        }
+        let mut data = vec![0u8; data_len];
+        tokio::time::timeout(timeout, stream.read_exact(&mut data)).await??;
+
+        if header.kind != agwpe::KIND_RAW {
+            continue; // version info, port capabilities etc.
+        }
+        // Raw frame data is prefixed with KISS type byte
+        let line = match ax25::Frame::decode(data.get(1..).unwrap_or_default()) {
+            Ok(frame) => frame.to_tnc2(),
+            Err(e) => {
+                log::warn!("{}: {}", server, e);
+                continue;
+            }
+        };
+        log::info!("{}: recv {:?}", server, line);
+
+        match tx.try_send(Received::new(server, line)) {
+            Ok(()) => (),
+            Err(_) => {
+                log::error!("busy, dropping packet");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiss;
+    use tokio::net::TcpListener;
+
+    const LINE: &str = "N0CALL-9>APDW16,WIDE1-1*:!4046.40N/11912.12W>Hi";
+
+    async fn serve_once(listener: TcpListener, protocol: Protocol) {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let frame = ax25::Frame::from_tnc2(LINE).unwrap().encode();
+        match protocol {
+            Protocol::Kiss => {
+                stream
+                    .write_all(&kiss::Frame::data(0, frame).encode())
+                    .await
+                    .unwrap();
+            }
+            Protocol::Agwpe => {
+                let mut hello = [0u8; agwpe::HEADER_LEN];
+                stream.read_exact(&mut hello).await.unwrap();
+                assert_eq!(hello[4], agwpe::KIND_RAW_ON);
+
+                let header = agwpe::Header {
+                    data_len: frame.len() as u32 + 1,
+                    ..agwpe::Header::new(0, agwpe::KIND_RAW)
+                };
+                stream.write_all(&header.encode()).await.unwrap();
+                stream.write_all(&[0]).await.unwrap();
+                stream.write_all(&frame).await.unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read() {
+        for protocol in [Protocol::Kiss, Protocol::Agwpe] {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let server = listener.local_addr().unwrap().to_string();
+            tokio::spawn(serve_once(listener, protocol));
+
+            let (tx, mut rx) = mpsc::channel(16);
+            let res = try_read(&server, protocol, None, tx).await;
+            assert!(res.is_err(), "should end with disconnect");
+            assert_eq!(rx.recv().await, Some(Received::new(&server, LINE)));
+        }
+    }
+}
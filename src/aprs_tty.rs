@@ -1,25 +1,37 @@
 use crate::err::{Error, Result};
+use crate::{ax25, io::aprs::Received, kiss, util::sync::recv_if_some};
 use std::borrow::Cow;
 use std::time::Duration;
-use tokio::{io::AsyncBufReadExt, sync::mpsc};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+};
 use tokio_serial::SerialPortBuilderExt;
 
 pub async fn read<'a>(
     tty: impl Into<Cow<'a, str>>,
     baud_rate: u32,
-    tx: mpsc::Sender<String>,
+    kiss: bool,
+    mut outgoing: Option<mpsc::Receiver<String>>,
+    tx: mpsc::Sender<Received>,
 ) -> Result<()> {
     let tty = tty.into();
 
     loop {
-        if let Err(e) = try_read(&tty, baud_rate, tx.clone()).await {
+        if let Err(e) = try_read(&tty, baud_rate, kiss, outgoing.as_mut(), tx.clone()).await {
             log::error!("{}: {}", tty, e);
         }
         tokio::time::sleep(Duration::from_secs(3)).await;
     }
 }
 
-async fn try_read(tty: &str, baud_rate: u32, tx: mpsc::Sender<String>) -> Result<()> {
+async fn try_read(
+    tty: &str,
+    baud_rate: u32,
+    kiss: bool,
+    outgoing: Option<&mut mpsc::Receiver<String>>,
+    tx: mpsc::Sender<Received>,
+) -> Result<()> {
     let timeout = Duration::from_secs(180);
 
     let stream = tokio_serial::new(tty, baud_rate)
@@ -30,6 +42,10 @@ async fn try_read(tty: &str, baud_rate: u32, tx: mpsc::Sender<String>) -> Result
         .open_native_async()
         .map_err(|e| Error::Other(format!("{}: error opening serial port: {}", tty, e)))?;
 
+    if kiss {
+        return read_kiss(tty, stream, timeout, outgoing, tx).await;
+    }
+
     let mut lines = tokio::io::BufReader::new(stream).lines();
 
     while let Some(line) = tokio::time::timeout(timeout, lines.next_line()).await?? {
@@ -38,7 +54,7 @@ async fn try_read(tty: &str, baud_rate: u32, tx: mpsc::Sender<String>) -> Result
         }
         log::info!("{}: recv {:?}", tty, line);
 
-        match tx.try_send(line) {
+        match tx.try_send(Received::new(tty, line)) {
             Ok(()) => (),
             Err(_) => {
                 log::error!("busy, dropping packet");
@@ -47,3 +63,57 @@ async fn try_read(tty: &str, baud_rate: u32, tx: mpsc::Sender<String>) -> Result
     }
     Err(Error::Disconnected)
 }
+
+/// Reads KISS frames from the stream and forwards APRS packets in them as
+/// TNC2 text lines, transmits `outgoing` TNC2 lines if any
+pub async fn read_kiss(
+    name: &str,
+    stream: impl AsyncRead + AsyncWrite + Unpin,
+    timeout: Duration,
+    mut outgoing: Option<&mut mpsc::Receiver<String>>,
+    tx: mpsc::Sender<Received>,
+) -> Result<()> {
+    let (mut stream_rx, mut stream_tx) = tokio::io::split(stream);
+    let mut decoder = kiss::Decoder::new();
+    let mut buf = [0u8; 1024];
+
+    loop {
+        let n = tokio::select! {
+            n = tokio::time::timeout(timeout, stream_rx.read(&mut buf)) => n??,
+            Some(line) = recv_if_some(&mut outgoing) => {
+                match ax25::Frame::from_tnc2(&line) {
+                    Ok(frame) => {
+                        log::info!("{}: send {:?}", name, line);
+                        let data = kiss::Frame::data(0, frame.encode()).encode();
+                        stream_tx.write_all(&data).await?;
+                    }
+                    Err(e) => log::error!("{}: can't send {:?}: {}", name, line, e),
+                }
+                continue;
+            }
+        };
+        if n == 0 {
+            return Err(Error::Disconnected);
+        }
+        for frame in decoder.push(&buf[..n]) {
+            if !frame.is_data() {
+                continue; // TNC parameters echoed back etc.
+            }
+            let line = match ax25::Frame::decode(&frame.data) {
+                Ok(frame) => frame.to_tnc2(),
+                Err(e) => {
+                    log::warn!("{}: {}", name, e);
+                    continue;
+                }
+            };
+            log::info!("{}: recv {:?}", name, line);
+
+            match tx.try_send(Received::new(name, line)) {
+                Ok(()) => (),
+                Err(_) => {
+                    log::error!("busy, dropping packet");
+                }
+            }
+        }
+    }
+}
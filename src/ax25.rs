@@ -0,0 +1,229 @@
+//! AX.25 UI frames, i.e. what APRS packets look like on the air, and their
+//! conversion to and from TNC2 text format (`SRC>DST,PATH:info`)
+
+use crate::err::{Error, Result};
+
+pub const CONTROL_UI: u8 = 0x03;
+pub const PID_NO_L3: u8 = 0xf0;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Address {
+    pub callsign: String,
+    pub ssid: u8,
+    /// "Has been repeated" bit, only meaningful for digipeater addresses
+    pub repeated: bool,
+}
+
+impl Address {
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != 7 {
+            return Err(bad_frame("truncated address"));
+        }
+        let mut callsign = String::with_capacity(6);
+        for &b in &bytes[..6] {
+            let c = (b >> 1) as char;
+            if c == ' ' {
+                break;
+            }
+            if !c.is_ascii_alphanumeric() {
+                return Err(bad_frame("bad callsign character"));
+            }
+            callsign.push(c);
+        }
+        if callsign.is_empty() {
+            return Err(bad_frame("empty callsign"));
+        }
+        Ok(Self {
+            callsign,
+            ssid: (bytes[6] >> 1) & 0x0f,
+            repeated: bytes[6] & 0x80 != 0,
+        })
+    }
+
+    /// High bit is the "has been repeated" bit for digipeaters and the
+    /// command/response bit for source and destination addresses
+    fn encode(&self, high_bit: bool, last: bool) -> Vec<u8> {
+        let mut res = self
+            .callsign
+            .bytes()
+            .chain(std::iter::repeat(b' '))
+            .take(6)
+            .map(|b| b << 1)
+            .collect::<Vec<u8>>();
+        res.push(0x60 | ((self.ssid & 0x0f) << 1) | if high_bit { 0x80 } else { 0 } | last as u8);
+        res
+    }
+}
+
+impl std::str::FromStr for Address {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (s, repeated) = match s.strip_suffix('*') {
+            Some(s) => (s, true),
+            None => (s, false),
+        };
+        let (callsign, ssid) = match s.split_once('-') {
+            Some((call, ssid)) => (
+                call,
+                ssid.parse::<u8>()
+                    .ok()
+                    .filter(|&v| v < 16)
+                    .ok_or_else(|| bad_frame("bad SSID"))?,
+            ),
+            None => (s, 0),
+        };
+        if callsign.is_empty()
+            || callsign.len() > 6
+            || !callsign.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            return Err(bad_frame("bad callsign"));
+        }
+        Ok(Self {
+            callsign: callsign.to_ascii_uppercase(),
+            ssid,
+            repeated,
+        })
+    }
+}
+
+impl std::fmt::Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.callsign)?;
+        if self.ssid != 0 {
+            write!(f, "-{}", self.ssid)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub dst: Address,
+    pub src: Address,
+    pub digis: Vec<Address>,
+    pub info: Vec<u8>,
+}
+
+impl Frame {
+    /// Decodes UI frame (without FCS, as KISS TNCs deliver it)
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        // Address field ends with the byte that has the lowest bit set
+        let addr_len = bytes
+            .iter()
+            .position(|b| b & 0x01 != 0)
+            .map(|n| n + 1)
+            .ok_or_else(|| bad_frame("unterminated address field"))?;
+        if addr_len % 7 != 0 || !(14..=70).contains(&addr_len) {
+            return Err(bad_frame("bad address field length"));
+        }
+        let mut addrs = bytes[..addr_len]
+            .chunks(7)
+            .map(Address::decode)
+            .collect::<Result<Vec<_>>>()?;
+        let digis = addrs.split_off(2);
+        let mut src = addrs.pop().unwrap();
+        let mut dst = addrs.pop().unwrap();
+        src.repeated = false; // these are command/response bits, not H bits
+        dst.repeated = false;
+
+        match bytes.get(addr_len..addr_len + 2) {
+            Some(&[CONTROL_UI, PID_NO_L3]) => (),
+            Some(_) => return Err(bad_frame("not an APRS UI frame")),
+            None => return Err(bad_frame("truncated frame")),
+        }
+        Ok(Self {
+            dst,
+            src,
+            digis,
+            info: bytes[addr_len + 2..].to_vec(),
+        })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut res = vec![];
+        // APRS packets are AX.25 v2 commands
+        res.extend(self.dst.encode(true, false));
+        res.extend(self.src.encode(false, self.digis.is_empty()));
+        for (n, digi) in self.digis.iter().enumerate() {
+            res.extend(digi.encode(digi.repeated, n + 1 == self.digis.len()));
+        }
+        res.push(CONTROL_UI);
+        res.push(PID_NO_L3);
+        res.extend(&self.info);
+        res
+    }
+
+    /// Formats frame as TNC2 monitor line, e.g. `N0CALL-9>APRS,WIDE1-1*:!...`
+    pub fn to_tnc2(&self) -> String {
+        let mut res = format!("{}>{}", self.src, self.dst);
+        // Only the last repeated digipeater gets the asterisk
+        let last_repeated = self.digis.iter().rposition(|d| d.repeated);
+        for (n, digi) in self.digis.iter().enumerate() {
+            res.push(',');
+            res.push_str(&digi.to_string());
+            if Some(n) == last_repeated {
+                res.push('*');
+            }
+        }
+        res.push(':');
+        res.push_str(&String::from_utf8_lossy(&self.info));
+        res
+    }
+
+    pub fn from_tnc2(line: &str) -> Result<Self> {
+        let (header, info) = line
+            .split_once(':')
+            .ok_or_else(|| bad_frame("missing info field"))?;
+        let (src, path) = header
+            .split_once('>')
+            .ok_or_else(|| bad_frame("missing destination"))?;
+        let mut path = path.split(',');
+        let dst = path.next().unwrap_or_default().parse()?;
+        let mut digis = path.map(|s| s.parse()).collect::<Result<Vec<Address>>>()?;
+        // In TNC2 format asterisk marks the last repeated digi, but in AX.25
+        // all digis before it have H bit set too
+        if let Some(n) = digis.iter().rposition(|d| d.repeated) {
+            digis[..n].iter_mut().for_each(|d| d.repeated = true);
+        }
+        Ok(Self {
+            src: src.parse()?,
+            dst,
+            digis,
+            info: info.as_bytes().to_vec(),
+        })
+    }
+}
+
+fn bad_frame(why: &str) -> Error {
+    Error::AprsParse {
+        what: "AX.25 frame".into(),
+        why: why.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tnc2_roundtrip() {
+        let line = "N0CALL-9>APDR15,WIDE1-1*,WIDE2-1:=4046.40N/11912.12W>Hi";
+        let frame = Frame::from_tnc2(line).unwrap();
+        let bytes = frame.encode();
+        assert_eq!(&bytes[0..7], &[0x82, 0xa0, 0x88, 0xa4, 0x62, 0x6a, 0xe0]);
+        assert_eq!(bytes[27], 0x63); // WIDE2-1 terminates the address field
+        let decoded = Frame::decode(&bytes).unwrap();
+        assert_eq!(decoded, frame);
+        assert_eq!(decoded.to_tnc2(), line);
+    }
+
+    #[test]
+    fn test_decode_rejects_non_ui() {
+        let mut bytes = Frame::from_tnc2("N0CALL>APRS:>test").unwrap().encode();
+        assert!(Frame::decode(&bytes).is_ok());
+        bytes[14] = 0x3f; // SABM
+        assert!(Frame::decode(&bytes).is_err());
+        assert!(Frame::decode(&bytes[..10]).is_err());
+    }
+}
@@ -0,0 +1,170 @@
+//! Our own position beacon and objects, formatted as TNC2 lines ready to be
+//! handed to a TNC
+
+use crate::{
+    aprs::Symbol,
+    err::{Error, Result},
+    util::{geo::Point, time::Timestamp},
+};
+use std::time::Duration;
+
+/// Experimental tocall, see http://www.aprs.org/aprs11/tocalls.txt
+pub static TOCALL: &str = "APZLPK";
+pub static DEFAULT_PATH: &str = "WIDE1-1";
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(20 * 60);
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub callsign: String,
+    pub location: Point,
+    pub symbol: Symbol,
+    pub comment: String,
+    pub interval: Duration,
+    pub path: String,
+    pub objects: Vec<Object>,
+}
+
+impl Config {
+    pub fn new(callsign: impl Into<String>, location: Point) -> Self {
+        Self {
+            callsign: callsign.into(),
+            location,
+            symbol: Symbol::new('/', '-'),
+            comment: String::new(),
+            interval: DEFAULT_INTERVAL,
+            path: DEFAULT_PATH.into(),
+            objects: vec![],
+        }
+    }
+
+    /// Position report without timestamp, e.g. `!4046.40N/11912.12W-comment`
+    pub fn position_packet(&self) -> String {
+        self.packet(format!(
+            "!{}{}",
+            format_location(self.location, self.symbol),
+            self.comment
+        ))
+    }
+
+    /// Object reports, e.g. `;CENTER   *092345z4046.40N/11912.12W;comment`
+    pub fn object_packets(&self, now: Timestamp) -> Vec<String> {
+        let dt = ::time::OffsetDateTime::from(now);
+        let dhm = format!("{:02}{:02}{:02}z", dt.day(), dt.hour(), dt.minute());
+        self.objects
+            .iter()
+            .map(|obj| {
+                self.packet(format!(
+                    ";{:<9}*{}{}{}",
+                    obj.name,
+                    dhm,
+                    format_location(obj.location, obj.symbol),
+                    obj.comment
+                ))
+            })
+            .collect()
+    }
+
+    fn packet(&self, info: String) -> String {
+        packet(&self.callsign, &self.path, &info)
+    }
+}
+
+/// Formats TNC2 line for a packet we send
+pub fn packet(callsign: &str, path: &str, info: &str) -> String {
+    if path.is_empty() {
+        format!("{}>{}:{}", callsign, TOCALL, info)
+    } else {
+        format!("{}>{},{}:{}", callsign, TOCALL, path, info)
+    }
+}
+
+/// APRS object we announce on behalf of some landmark
+#[derive(Debug, Clone, PartialEq)]
+pub struct Object {
+    pub name: String,
+    pub location: Point,
+    pub symbol: Symbol,
+    pub comment: String,
+}
+
+impl std::str::FromStr for Object {
+    type Err = Error;
+
+    /// Parses `NAME@LAT,LNG` with optional two-character symbol and comment,
+    /// e.g. `CtrCamp@40.7807,-119.2112,/;,Coffee`
+    fn from_str(s: &str) -> Result<Self> {
+        let bad = || Error::BadRequest(format!("bad object {:?}, expected NAME@LAT,LNG", s));
+        let (name, rest) = s.split_once('@').ok_or_else(bad)?;
+        let mut parts = rest.splitn(4, ',');
+        let lat = parts
+            .next()
+            .and_then(|v| v.trim().parse().ok())
+            .ok_or_else(bad)?;
+        let lng = parts
+            .next()
+            .and_then(|v| v.trim().parse().ok())
+            .ok_or_else(bad)?;
+        let symbol = match parts.next() {
+            Some(v) => v.parse::<Symbol>().map_err(|_| bad())?,
+            None => Symbol::new('/', ';'),
+        };
+        let name = name.trim();
+        if name.is_empty() || name.len() > 9 || !symbol.is_valid() {
+            return Err(bad());
+        }
+        Ok(Self {
+            name: name.into(),
+            location: Point::new(lng, lat)?,
+            symbol,
+            comment: parts.next().unwrap_or_default().into(),
+        })
+    }
+}
+
+/// Formats uncompressed position with symbol, e.g. `4046.40N/11912.12W-`
+pub fn format_location(location: Point, symbol: Symbol) -> String {
+    let (lat, lng) = (location.lat(), location.lng());
+    // Round to hundredths of minute first, so we never print 60.00 minutes
+    let lat_hmin = (lat.abs() * 6000.).round() as u32;
+    let lng_hmin = (lng.abs() * 6000.).round() as u32;
+    format!(
+        "{:02}{:02}.{:02}{}{}{:03}{:02}.{:02}{}{}",
+        lat_hmin / 6000,
+        lat_hmin % 6000 / 100,
+        lat_hmin % 100,
+        if lat < 0. { 'S' } else { 'N' },
+        symbol.table,
+        lng_hmin / 6000,
+        lng_hmin % 6000 / 100,
+        lng_hmin % 100,
+        if lng < 0. { 'W' } else { 'E' },
+        symbol.code,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aprs::Packet;
+
+    #[test]
+    fn test_packets() {
+        let mut config = Config::new("K6CQU-5", Point::new(-119.202, 40.7734).unwrap());
+        config.comment = "Live Playa kiosk".into();
+        config.objects = vec!["CtrCamp@40.7807,-119.2112,/;,Coffee".parse().unwrap()];
+
+        let beacon = config.position_packet();
+        assert_eq!(
+            beacon,
+            "K6CQU-5>APZLPK,WIDE1-1:!4046.40N/11912.12W-Live Playa kiosk"
+        );
+        assert!(matches!(Packet::parse(beacon), Ok(Packet::Position(_))));
+
+        let now = Timestamp::from_calendar_utc(2023, 8, 29, 17, 5, 0).unwrap();
+        let objects = config.object_packets(now);
+        assert_eq!(
+            objects,
+            vec!["K6CQU-5>APZLPK,WIDE1-1:;CtrCamp  *291705z4046.84N/11912.67W;Coffee"]
+        );
+    }
+}
@@ -36,12 +36,6 @@ pub enum Error {
     #[error("can't parse APRS packet {what}: {why}")]
     AprsParse { what: String, why: String },
 
-    #[error("APRS packets are supposed to be in chronological order; {new_ts} < {last_ts}")]
-    AprsOrder {
-        new_ts: String,
-        last_ts: String,
-    },
-
     #[error("bad request: {0}")]
     BadRequest(String),
 
@@ -133,6 +127,12 @@ impl std::convert::From<tokio::time::error::Elapsed> for Error {
     }
 }
 
+impl std::convert::From<rusqlite::Error> for Error {
+    fn from(value: rusqlite::Error) -> Self {
+        Error::OtherWithContext("sqlite", value.to_string())
+    }
+}
+
 impl std::convert::From<serde_json::Error> for Error {
     fn from(value: serde_json::Error) -> Self {
         Error::Other(format!("failed to serialize {:?}", value))
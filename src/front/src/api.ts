@@ -1,5 +1,10 @@
 const ENDPOINT = "/api";
 
+// URL of map icon for APRS symbol with given id (see `icon` feature property)
+export function symbolIconUrl(id: string): string {
+  return `${ENDPOINT}/v0/symbols/${id}.svg`;
+}
+
 export type LngLat = [number, number];
 export type BBox = [number, number, number, number];
 export type Listener = () => void;
@@ -8,6 +13,11 @@ export interface Query {
   feature?: string,
   bounds?: BBox;
   zoom?: number;
+  // Minutes of track history to show behind stations
+  trail?: number;
+  // Moment in the past to show instead of now, and how fast to move on
+  timeMs?: number;
+  speed?: number;
 }
 
 export interface MapView {
@@ -16,9 +26,14 @@ export interface MapView {
   description: string,
   features: any[];
   refs: FeatureRef[];
+  weather: WeatherReport[];
+  bulletins: Bulletin[];
+  outbox: OutgoingMessage[];
   log: LogMessage[];
   name: string,
-  time: object;
+  time: string;
+  // Set when playing back the past
+  speed?: number;
   // bounds: BBox;
   type: "FeatureCollection";
   zoom: number;
@@ -37,15 +52,58 @@ export interface BeaconRef {
   slug: string, 
   location: string, 
   lastseen: string,
+  source?: string,
+  digipeater?: string,
+}
+
+export interface ObjectRef {
+  type: "object",
+  name: string,
+  slug: string,
+  location: string,
+  lastseen: string,
+  reporter: string,
+}
+
+export type FeatureRef = BeaconRef | ObjectRef;
+
+export interface WeatherReport {
+  name: string,
+  slug: string,
+  location?: string,
+  time: string,
+  windDirDeg?: number,
+  windSpeedMps?: number,
+  windGustMps?: number,
+  temperatureC?: number,
+  humidityPct?: number,
+  pressureHpa?: number,
+}
+
+export interface Bulletin {
+  id: string,
+  from: string,
+  text: string,
+  time: string,
 }
 
-export type FeatureRef = BeaconRef;
+export interface OutgoingMessage {
+  id: number,
+  to: string,
+  text: string,
+  time: string,
+  state: "pending" | "acked" | "rejected" | "failed",
+  attempts: number,
+}
 
 export interface LogMessage {
   id: number,
   level: "error"|"info"|"debug",
   text: string,
   time: string,
+  source?: string,
+  dups: number,
+  paths: string[],
 }
 
 
@@ -81,6 +139,7 @@ export class Session {
     this._fetchAndNotify();
     if (refreshInterval) {
       setInterval(() => {
+        this._advancePlayback(refreshInterval);
         this._fetchAndNotify();
       }, refreshInterval);
     }
@@ -94,6 +153,30 @@ export class Session {
     this._fetchAndNotify();
   }
 
+  async sendMessage(to: string, text: string) {
+    try {
+      const res = await fetch(`${ENDPOINT}/v0/messages`, {
+        method: "POST",
+        headers: { "Content-Type": "application/json" },
+        body: JSON.stringify({ to, text }),
+      });
+      const body = await res.json();
+      if (!res.ok) {
+        throw new Error(body.message || `HTTP ${res.status}`);
+      }
+      this.state = {
+        ...this.state,
+        alert: new Alert(`Message to ${body.to} queued`, "success"),
+      };
+    } catch (e) {
+      this.state = {
+        ...this.state,
+        alert: new Alert(`Failed to send message: ${e instanceof Error ? e.message : e}`, "error"),
+      };
+    }
+    this._fetchAndNotify();
+  }
+
   dismissAlert() {
     const { alert, ...rest } = this.state;
     this.state = rest;
@@ -112,6 +195,19 @@ export class Session {
     }
   }
 
+  // Moves playback time along, back to live once it catches up with now
+  protected _advancePlayback(elapsedMs: number) {
+    const { timeMs, speed, ...rest } = this.state.query;
+    if (!timeMs) {
+      return;
+    }
+    const nextMs = timeMs + elapsedMs * (speed || 1);
+    this.state = {
+      ...this.state,
+      query: nextMs < Date.now() ? { ...rest, timeMs: nextMs, speed } : rest,
+    };
+  }
+
   protected async _fetchAndNotify() {
     if (this._loading) {
       this._loading.abort();
@@ -125,8 +221,10 @@ export class Session {
     try {
       const q = this.state.query;
       const args = new URLSearchParams();
-      // q.timeMs && args.append("time", new Date(q.timeMs).toISOString());
+      q.timeMs && args.append("time", new Date(q.timeMs).toISOString());
+      q.speed && args.append("speed", q.speed.toString());
       q.zoom && args.append("zoom", q.zoom.toString());
+      q.trail && args.append("trail", q.trail.toString());
       // q.bounds &&
       //   args.append("bounds", q.bounds.map((v) => v.toFixed(5)).join(","));
       const res = await fetch(`${ENDPOINT}/v0/?${args}`, {
@@ -19,6 +19,8 @@ export default function ListView() {
                     <div className="name"><b>{ref.name}</b></div>
                     <div className="location">{ref.location}</div>
                     <div className="lastseen"><Timestamp time={ref.lastseen} /></div>
+                    {ref.type === "beacon" && ref.source &&
+                        <div className="heard">{ref.source}{ref.digipeater && ` via ${ref.digipeater}`}</div>}
                 </div>
             ))}
         </div>
@@ -17,7 +17,7 @@ export default function LogView() {
         <table className="log">
             <tbody>
                 {log.map((entry) => (
-                    <tr key={entry.id} className={entry.level}><td>{entry.time}&nbsp;</td><td>{entry.text}</td></tr>
+                    <tr key={entry.id} className={entry.level}><td>{entry.time}&nbsp;</td><td>{entry.source}&nbsp;</td><td>{entry.text}{entry.dups > 0 && <span className="dups" title={entry.paths.join("\n")}>&nbsp;(+{entry.dups})</span>}</td></tr>
                 ))}
             </tbody>
         </table>
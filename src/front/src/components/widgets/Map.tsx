@@ -2,6 +2,7 @@ import * as react from 'react';
 import * as maplibre from 'maplibre-gl';
 import * as mapstyle from './MapStyle';
 import type * as api from '../../api';
+import { symbolIconUrl } from '../../api';
 import tracker from '../../assets/tracker02.png';
 
 interface MapProps {
@@ -63,6 +64,20 @@ export default function Map({ data: map, onMove, className, id }: MapProps) {
           });
           }
       });
+      // APRS symbol icons are rendered by the server on demand
+      mapRef.current.on('styleimagemissing', (e) => {
+        const id: string = e.id;
+        if (!id.startsWith('aprs-')) {
+          return;
+        }
+        const img = new Image(32, 32);
+        img.onload = () => {
+          if (mapRef.current && !mapRef.current.hasImage(id)) {
+            mapRef.current.addImage(id, img);
+          }
+        };
+        img.src = symbolIconUrl(id.substring(5));
+      });
     }
     else if (mapRef.current) {
       // Update map
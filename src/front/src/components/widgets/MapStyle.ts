@@ -301,6 +301,22 @@ export function getMapStyle(
       //     "text-halo-color": COLORS.bgcolor01,
       //   },
       // },
+      {
+        id: "trails",
+        source: "mapdata",
+        type: "line",
+        filter: ["==", ["get", "liveplaya"], "trail"],
+        layout: {
+          "line-cap": "round",
+          "line-join": "round",
+        },
+        paint: {
+          "line-color": ["case", ["get", "_fav"], '#ff5555', COLORS.fgcolor02],
+          "line-width": 2,
+          "line-opacity": 0.6,
+          "line-dasharray": [2, 2],
+        },
+      },
       {
         id: "pois",
         source: "mapdata", // rtfeatures
@@ -313,17 +329,20 @@ export function getMapStyle(
         },
         layout: {
           "symbol-sort-key": ["get", "priority"],
-          "icon-image": "tracker",
+          "icon-image": ["coalesce", ["get", "icon"], "tracker"],
           "icon-size": [
-            "match",
-            ["get", "size"],
-            "small",
-            0.3,
-            "large",
-            0.7,
-            0.5,
+            "case",
+            ["to-boolean", ["get", "icon"]],
+            ["match", ["get", "size"], "small", 0.6, "large", 1.2, 0.9],
+            ["match", ["get", "size"], "small", 0.3, "large", 0.7, 0.5],
+          ],
+          // APRS symbol icons are drawn upright, only the tracker arrow rotates
+          "icon-rotate": [
+            "case",
+            ["to-boolean", ["get", "icon"]],
+            0,
+            ["coalesce", ["get", "headingDeg"], 0],
           ],
-          "icon-rotate": ["get", "headingDeg"],
           //"icon-overlap": "always",
           //"symbol-sort-key": ["get", "order"],
           "text-field": ["concat", ["get", "name"], "\n", ["get", "location"]],
@@ -347,6 +366,27 @@ export function getMapStyle(
           "text-halo-color": COLORS.bgcolor01,
         },
       },
+      {
+        id: "objects",
+        source: "mapdata",
+        type: "symbol",
+        filter: ["==", ["get", "poi"], "object"],
+        layout: {
+          "icon-image": ["coalesce", ["get", "icon"], ""],
+          "icon-size": 0.7,
+          "text-field": ["concat", ["get", "name"], "\n", ["get", "location"]],
+          "text-optional": true,
+          "text-anchor": "center",
+          "text-offset": [0, 1.8],
+          "text-allow-overlap": false,
+          "text-size": 12,
+        },
+        paint: {
+          "text-color": COLORS.fgcolor02,
+          "text-halo-width": 1.5,
+          "text-halo-color": COLORS.bgcolor01,
+        },
+      },
     ],
   };
   if (!rastermap) {
@@ -9,6 +9,8 @@ export interface NavQuery {
   timeMs?: number;
   center?: api.LngLat;
   zoom?: number;
+  trail?: number;
+  speed?: number;
 }
 
 
@@ -55,6 +57,13 @@ export function useNavQuery(): [NavQuery, (q: NavQuery) => void] {
   let zoomStr = params.get("z");
   let zoom = zoomStr === null ? undefined : parseFloat(zoomStr);
 
+  // Not touched by navigate(), so whoever set up the kiosk picks it
+  let trailStr = params.get("trail");
+  let trail = trailStr === null ? undefined : parseInt(trailStr);
+
+  let speedStr = params.get("speed");
+  let speed = speedStr === null ? undefined : parseFloat(speedStr);
+
   let centerRaw = params.get("c")?.split("_").map(parseFloat);
   let center: api.LngLat | undefined;
   if (centerRaw?.length == 2 && !centerRaw.some(isNaN)) {
@@ -64,9 +73,11 @@ export function useNavQuery(): [NavQuery, (q: NavQuery) => void] {
     center = undefined;
   }
   const query = {
-    ts,
+    timeMs: ts,
     center,
     zoom,
+    trail,
+    speed,
   };
   return [query, navigate];
 }
@@ -84,11 +95,13 @@ export function useSession() {
     // rendered, so we don't know it's size and thus the map bounds - but we'll
     // create some dummy bounds instead.
     console.log(`initial nav query:`, JSON.stringify(navQuery));
-    const { center: c, timeMs, zoom } = navQuery;
+    const { center: c, timeMs, zoom, trail, speed } = navQuery;
     const apiQuery = {
       bounds: c ? ([c[0], c[1], c[0], c[1]] as api.BBox) : undefined,
       zoom,
       timeMs,
+      trail,
+      speed,
     };
     console.log(`initial api query:`, JSON.stringify(apiQuery));
     theSession = new api.Session(apiQuery, 5000);
@@ -0,0 +1,150 @@
+//! HDLC framing of AX.25 frames on the air: flags, bit stuffing and the
+//! frame check sequence, see http://www.ax25.net/AX25.2.2-Jul%2098-2.pdf
+
+pub const FLAG: u8 = 0x7e;
+
+/// Shortest frame worth looking at: two addresses, control, PID and FCS
+pub const MIN_FRAME_LEN: usize = 14 + 2 + 2;
+pub const MAX_FRAME_LEN: usize = 1024;
+
+/// Frame check sequence, i.e. CRC-16/X.25
+pub fn fcs(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xffff;
+    for &b in data {
+        crc ^= b as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0x8408
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Returns bits to send for the frame (least significant bit of every byte
+/// first), i.e. stuffed frame with FCS between opening and closing flags
+pub fn encode(frame: &[u8], preamble_flags: usize) -> Vec<bool> {
+    let byte_bits = |b: u8| (0..8).map(move |i| b & (1 << i) != 0);
+    let mut res = vec![];
+    for _ in 0..preamble_flags.max(1) {
+        res.extend(byte_bits(FLAG));
+    }
+    let fcs = fcs(frame).to_le_bytes();
+    let mut ones = 0;
+    for bit in frame.iter().chain(fcs.iter()).flat_map(|&b| byte_bits(b)) {
+        res.push(bit);
+        if bit {
+            ones += 1;
+            if ones == 5 {
+                res.push(false);
+                ones = 0;
+            }
+        } else {
+            ones = 0;
+        }
+    }
+    res.extend(byte_bits(FLAG));
+    res
+}
+
+/// Incremental HDLC decoder, feed it demodulated bits and collect frames
+/// that pass the FCS check (without the FCS)
+#[derive(Debug, Default)]
+pub struct Decoder {
+    /// Last 8 bits received, most recent one in the high bit
+    pattern: u8,
+    /// Bits received since the last flag, unstuffed
+    bits: Vec<bool>,
+    ones: u32,
+    in_frame: bool,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, bit: bool) -> Option<Vec<u8>> {
+        self.pattern = (self.pattern >> 1) | if bit { 0x80 } else { 0 };
+        if self.pattern == FLAG {
+            let res = self.take();
+            self.in_frame = true;
+            self.ones = 0;
+            return res;
+        }
+        if self.pattern & 0xfe == 0xfe {
+            // Seven ones in a row is an abort (or just noise)
+            self.in_frame = false;
+            self.bits.clear();
+            return None;
+        }
+        if !self.in_frame {
+            return None;
+        }
+        if bit {
+            self.ones += 1;
+        } else {
+            let stuffed = self.ones == 5;
+            self.ones = 0;
+            if stuffed {
+                return None;
+            }
+        }
+        self.bits.push(bit);
+        if self.bits.len() > MAX_FRAME_LEN * 8 {
+            self.in_frame = false;
+            self.bits.clear();
+        }
+        None
+    }
+
+    fn take(&mut self) -> Option<Vec<u8>> {
+        let mut bits = std::mem::take(&mut self.bits);
+        // The first 7 bits of the closing flag have made it in already
+        bits.truncate(bits.len().saturating_sub(7));
+        if !bits.len().is_multiple_of(8) || bits.len() < MIN_FRAME_LEN * 8 {
+            return None; // back-to-back flags or noise
+        }
+        let bytes = bits
+            .chunks(8)
+            .map(|byte| {
+                byte.iter()
+                    .enumerate()
+                    .fold(0u8, |acc, (i, &bit)| acc | (bit as u8) << i)
+            })
+            .collect::<Vec<u8>>();
+        let (data, fcs) = bytes.split_at(bytes.len() - 2);
+        if self::fcs(data).to_le_bytes() != fcs {
+            return None;
+        }
+        Some(data.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        assert_eq!(fcs(b"123456789"), 0x906e);
+
+        // Lots of ones, so that there's something to stuff
+        let frame = [0xff, 0x7e, 0xfc, 0x00, 0x01, 0x3f, 0xff, 0xff, 0x55]
+            .iter()
+            .cycle()
+            .take(40)
+            .copied()
+            .collect::<Vec<u8>>();
+        let mut decoder = Decoder::new();
+        let mut frames = vec![];
+        for _ in 0..2 {
+            for bit in encode(&frame, 3) {
+                frames.extend(decoder.push(bit));
+            }
+        }
+        assert_eq!(frames, vec![frame.clone(), frame]);
+    }
+}
@@ -0,0 +1,119 @@
+//! RF to APRS-IS gateway, see http://www.aprs-is.net/IGateDetails.aspx and
+//! http://www.aprs-is.net/q.aspx
+
+use crate::aprs::{dupe_key, DUPE_WINDOW};
+use crate::err::Result;
+use crate::io::aprs::Received;
+use std::collections::HashMap;
+use std::time::Instant;
+use tokio::sync::mpsc;
+
+/// Path elements that mean "do not put me on the Internet"
+const NO_GATE: [&str; 4] = ["TCPIP", "TCPXX", "NOGATE", "RFONLY"];
+
+/// Returns the packet rewritten for APRS-IS, i.e. with `qAR,<igate>`
+/// appended to its path, or `None` if it must not be gated
+pub fn gate(line: &str, igate_call: &str) -> Option<String> {
+    let (header, info) = line.split_once(':')?;
+    let (src, path) = header.split_once('>')?;
+    if src.is_empty() || path.is_empty() {
+        return None;
+    }
+    let path_elems = path.split(',').map(|s| s.trim_end_matches('*'));
+    for elem in path_elems.skip(1) {
+        if NO_GATE.contains(&elem) || elem.starts_with("qA") {
+            return None; // already been on the Internet or asked not to be
+        }
+    }
+    // Third party packets came from the Internet in the first place, general
+    // queries are meant for stations in RF range only
+    if info.starts_with('}') || info.starts_with("?APRS?") || info.starts_with("?IGATE?") {
+        return None;
+    }
+    Some(format!("{},qAR,{}:{}", header, igate_call, info))
+}
+
+#[derive(Debug, Default)]
+pub struct Dedup {
+    seen: HashMap<String, Instant>,
+}
+
+impl Dedup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns true if the packet has already been seen within the window,
+    /// records it otherwise
+    pub fn is_dupe(&mut self, line: &str, now: Instant) -> bool {
+        self.seen
+            .retain(|_, ts| now.duration_since(*ts) < DUPE_WINDOW);
+        match self.seen.entry(dupe_key(line)) {
+            std::collections::hash_map::Entry::Occupied(_) => true,
+            std::collections::hash_map::Entry::Vacant(e) => {
+                e.insert(now);
+                false
+            }
+        }
+    }
+}
+
+/// Passes packets heard on RF on to the server and forwards the ones that
+/// qualify to the APRS-IS uplink
+pub async fn run(
+    igate_call: String,
+    mut rf_rx: mpsc::Receiver<Received>,
+    server_tx: mpsc::Sender<Received>,
+    uplink_tx: mpsc::Sender<String>,
+) -> Result<()> {
+    let mut dedup = Dedup::new();
+    while let Some(rx) = rf_rx.recv().await {
+        if let Some(gated) = gate(&rx.data, &igate_call) {
+            if dedup.is_dupe(&rx.data, Instant::now()) {
+                log::debug!("igate: not gating duplicate {:?}", rx.data);
+            } else if uplink_tx.try_send(gated).is_err() {
+                log::error!("igate: uplink busy, dropping packet");
+            }
+        }
+        server_tx.send(rx).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_gate() {
+        assert_eq!(
+            gate(
+                "TGECKO>APDR15,WIDE1-1*,WIDE2-1:=4046.40N/11912.12W>",
+                "K6CQU-5"
+            )
+            .as_deref(),
+            Some("TGECKO>APDR15,WIDE1-1*,WIDE2-1,qAR,K6CQU-5:=4046.40N/11912.12W>")
+        );
+        assert_eq!(gate("TGECKO>APRS,TCPIP*:>hi", "K6CQU-5"), None);
+        assert_eq!(gate("TGECKO>APRS,RFONLY:>hi", "K6CQU-5"), None);
+        assert_eq!(gate("TGECKO>APRS,NOGATE:>hi", "K6CQU-5"), None);
+        assert_eq!(gate("TGECKO>APRS,qAR,N0CALL:>hi", "K6CQU-5"), None);
+        assert_eq!(
+            gate("K6CQU>APRS:}DUCK>APRS,TCPIP,K6CQU*:>hi", "K6CQU-5"),
+            None
+        );
+        assert_eq!(gate("garbage", "K6CQU-5"), None);
+    }
+
+    #[test]
+    fn test_dedup() {
+        let mut dedup = Dedup::new();
+        let t0 = Instant::now();
+        assert!(!dedup.is_dupe("DUCK>APRS,WIDE1-1:>hi", t0));
+        let t1 = t0 + Duration::from_secs(5);
+        assert!(dedup.is_dupe("DUCK>APRS,K6CQU-5*,WIDE1*:>hi", t1));
+        assert!(!dedup.is_dupe("DUCK>APRS,WIDE1-1:>bye", t1));
+        assert!(!dedup.is_dupe("DUCK>APRS,WIDE1-1:>hi", t0 + DUPE_WINDOW));
+    }
+}
@@ -70,3 +70,20 @@ pub struct PositionReport {
     pub comment: Option<String>,
 }
 
+/// Packet as it came from one of the inputs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Received {
+    /// Where it came from, e.g. serial port or APRS-IS server
+    pub source: String,
+    /// TNC2 text line
+    pub data: String,
+}
+
+impl Received {
+    pub fn new(source: impl Into<String>, data: impl Into<String>) -> Self {
+        Self {
+            source: source.into(),
+            data: data.into(),
+        }
+    }
+}
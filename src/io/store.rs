@@ -1,10 +1,155 @@
+use crate::{
+    err::{Error, Result},
+    util::time::{Duration, Timespan, Timestamp},
+};
 use serde::{Deserialize, Serialize};
 
+/// Where received packets are kept, e.g. [`crate::svc::jsonlog::JsonLog`] or
+/// [`crate::svc::sqlite::SqliteStore`]
+#[async_trait::async_trait]
+pub trait Store: Send + Sync {
+    async fn append(&mut self, ts: Timestamp, record: &Record) -> Result<()>;
+
+    /// Returns records within the span, oldest first
+    async fn records(&self, span: Timespan) -> Result<Box<dyn Cursor>>;
+
+    /// Returns records within the span sent by the station, oldest first
+    async fn records_from(&self, callsign: &str, span: Timespan) -> Result<Box<dyn Cursor>>;
+
+    /// Deletes records older than `ts`, or at least the bulk of them
+    async fn compact(&mut self, ts: Timestamp) -> Result<()>;
+
+    /// Puts records appended lately on disk if the fsync policy says it's
+    /// time, for those it lets wait for a later append
+    async fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// When to make the OS put written records on disk, so that they survive
+/// losing power
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Fsync {
+    /// After every record
+    #[default]
+    Always,
+    /// At most this long after a record is written; records written since
+    /// the last time can be lost
+    Every(Duration),
+    /// Whenever the OS decides to
+    Never,
+}
+
+impl std::str::FromStr for Fsync {
+    type Err = Error;
+
+    /// Parses `always`, `never` or number of seconds
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => match s.parse::<u64>() {
+                Ok(secs) => Ok(Self::Every(Duration::from_secs(secs))),
+                Err(_) => Err(Error::BadRequest(format!(
+                    "bad fsync policy {:?}, expected always, never or seconds",
+                    s
+                ))),
+            },
+        }
+    }
+}
+
+/// Records read from a store one at a time
+#[async_trait::async_trait]
+pub trait Cursor: Send {
+    /// Returns next record, `None` when there are no more
+    async fn next(&mut self) -> Result<Option<(Timestamp, Record)>>;
+}
+
+/// Records from a cursor that were sent by one station, for stores that
+/// have no better way to find them
+pub struct FromCallsign {
+    inner: Box<dyn Cursor>,
+    callsign: String,
+}
+
+impl FromCallsign {
+    pub fn new(inner: Box<dyn Cursor>, callsign: &str) -> Self {
+        let callsign = callsign.to_string();
+        Self { inner, callsign }
+    }
+}
+
+#[async_trait::async_trait]
+impl Cursor for FromCallsign {
+    async fn next(&mut self) -> Result<Option<(Timestamp, Record)>> {
+        while let Some((ts, rec)) = self.inner.next().await? {
+            let callsign = rec.callsign().unwrap_or_default();
+            if callsign.eq_ignore_ascii_case(&self.callsign) {
+                return Ok(Some((ts, rec)));
+            }
+        }
+        Ok(None)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 #[serde(tag = "type")]
 pub enum Record {
     #[serde(alias = "aprs-packet")]
     #[serde(rename = "aprs")]
-    AprsPacket { data: String },
+    AprsPacket {
+        data: String,
+        /// Input the packet came from, missing in older logs
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        source: Option<String>,
+        /// Digipeaters and q-construct, as in the packet's header
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        path: Vec<String>,
+        /// Digipeater we heard the packet from, if any
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        digipeater: Option<String>,
+    },
+}
+
+impl Record {
+    /// Station that sent the packet
+    pub fn callsign(&self) -> Option<&str> {
+        match self {
+            Record::AprsPacket { data, .. } => data.split_once('>').map(|(call, _)| call),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_old_records() {
+        let rec: Record =
+            serde_json::from_str(r#"{"type":"aprs","data":"DUCK>APRS:>hi"}"#).unwrap();
+        assert_eq!(rec.callsign(), Some("DUCK"));
+        match rec {
+            Record::AprsPacket {
+                data, source, path, ..
+            } => {
+                assert_eq!(data, "DUCK>APRS:>hi");
+                assert_eq!(source, None);
+                assert!(path.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn test_fsync() {
+        assert_eq!("always".parse::<Fsync>().unwrap(), Fsync::Always);
+        assert_eq!("never".parse::<Fsync>().unwrap(), Fsync::Never);
+        assert_eq!(
+            "10".parse::<Fsync>().unwrap(),
+            Fsync::Every(Duration::from_secs(10))
+        );
+        assert!("sometimes".parse::<Fsync>().is_err());
+    }
 }
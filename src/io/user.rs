@@ -1,4 +1,5 @@
 use crate::{
+    aprs::DeliveryState,
     err::Result,
     util::{geo::*, time::Timestamp},
 };
@@ -9,14 +10,31 @@ use tokio::sync::oneshot;
 #[derive(Debug)]
 pub enum Event {
     ViewRequest(Query, oneshot::Sender<Result<View>>),
+    SendMessage(NewMessage, oneshot::Sender<Result<OutgoingMessage>>),
+}
+
+/// APRS message somebody wants to send from the kiosk
+#[derive(Debug, serde::Deserialize)]
+pub struct NewMessage {
+    pub to: String,
+    pub text: String,
 }
 
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct Query {
+    /// Slug of the station being looked at; its trail goes as far back as
+    /// asked, not just as far as the others'
     pub feature: Option<String>,
     pub bounds: Option<crate::util::geo::BBox>,
     pub zoom: Option<f64>,
+    /// Minutes of track history to draw behind stations, none by default
+    pub trail: Option<u32>,
+    /// Show the map as it was at this moment rather than now
+    pub time: Option<Timestamp>,
+    /// How fast the client is playing back, with `time`. The client moves
+    /// `time` along itself; this is only checked and handed back.
+    pub speed: Option<f64>,
 }
 
 #[derive(Debug, Serialize)]
@@ -25,11 +43,17 @@ pub struct View {
     pub name: String,
     pub description: Option<String>,
     pub time: Timestamp,
+    /// Playback speed if this is a view of the past, so that the client can
+    /// move `time` along; the server doesn't play anything itself
+    pub speed: Option<f64>,
 
     #[serde(flatten)]
     pub map: Option<Map>,
 
     pub refs: Vec<FeatureRef>,
+    pub weather: Vec<Weather>,
+    pub bulletins: Vec<Bulletin>,
+    pub outbox: Vec<OutgoingMessage>,
     pub log: Vec<LogMessage>,
 }
 
@@ -53,9 +77,55 @@ pub enum FeatureRef {
         slug: String,
         location: String,
         lastseen: Timestamp,
+        /// Input we last heard the beacon on
+        source: Option<String>,
+        /// Digipeater that relayed it, if any
+        digipeater: Option<String>,
+    },
+    Object {
+        name: String,
+        slug: String,
+        location: String,
+        lastseen: Timestamp,
+        reporter: String,
     },
 }
 
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Weather {
+    pub name: String,
+    pub slug: String,
+    pub location: Option<String>,
+    pub time: Timestamp,
+    pub wind_dir_deg: Option<f64>,
+    pub wind_speed_mps: Option<f64>,
+    pub wind_gust_mps: Option<f64>,
+    pub temperature_c: Option<f64>,
+    pub humidity_pct: Option<f64>,
+    pub pressure_hpa: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bulletin {
+    pub id: String,
+    pub from: String,
+    pub text: String,
+    pub time: Timestamp,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutgoingMessage {
+    pub id: u64,
+    pub to: String,
+    pub text: String,
+    pub time: Timestamp,
+    pub state: DeliveryState,
+    pub attempts: u32,
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 #[serde(tag = "level")]
@@ -64,10 +134,19 @@ pub enum LogMessage {
         id: u64,
         time: Timestamp,
         text: String,
+        /// Input the first copy came from
+        source: Option<String>,
+        /// Number of copies heard after the first one
+        dups: u32,
+        /// Inputs and paths the packet was heard on
+        paths: Vec<String>,
     },
     Error {
         id: u64,
         time: Timestamp,
         text: String,
+        source: Option<String>,
+        dups: u32,
+        paths: Vec<String>,
     },
 }
@@ -0,0 +1,128 @@
+//! KISS framing as spoken by most hardware TNCs and Dire Wolf, see
+//! http://www.ax25.net/kiss.aspx
+
+pub const FEND: u8 = 0xc0;
+pub const FESC: u8 = 0xdb;
+pub const TFEND: u8 = 0xdc;
+pub const TFESC: u8 = 0xdd;
+
+pub const CMD_DATA: u8 = 0x00;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub port: u8,
+    pub command: u8,
+    pub data: Vec<u8>,
+}
+
+impl Frame {
+    pub fn data(port: u8, data: impl Into<Vec<u8>>) -> Self {
+        Self {
+            port,
+            command: CMD_DATA,
+            data: data.into(),
+        }
+    }
+
+    pub fn is_data(&self) -> bool {
+        self.command == CMD_DATA
+    }
+
+    /// Returns escaped frame, including leading and trailing FENDs
+    pub fn encode(&self) -> Vec<u8> {
+        let mut res = Vec::with_capacity(self.data.len() + 4);
+        res.push(FEND);
+        res.push((self.port << 4) | (self.command & 0x0f));
+        for &b in self.data.iter() {
+            match b {
+                FEND => res.extend([FESC, TFEND]),
+                FESC => res.extend([FESC, TFESC]),
+                b => res.push(b),
+            }
+        }
+        res.push(FEND);
+        res
+    }
+}
+
+/// Incremental KISS decoder, feed it whatever comes from the wire and
+/// collect complete frames
+#[derive(Debug, Default)]
+pub struct Decoder {
+    buf: Vec<u8>,
+    escaped: bool,
+    synced: bool,
+}
+
+impl Decoder {
+    /// Frames longer than this are garbage (or not KISS at all)
+    pub const MAX_FRAME_LEN: usize = 1024;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Frame> {
+        let mut res = vec![];
+        for &b in bytes {
+            match (self.escaped, b) {
+                (_, FEND) => {
+                    if let Some(frame) = self.take() {
+                        res.push(frame);
+                    }
+                    self.escaped = false;
+                    self.synced = true;
+                }
+                (false, FESC) => self.escaped = true,
+                (true, TFEND) => self.put(FEND),
+                (true, TFESC) => self.put(FESC),
+                (true, b) => self.put(b), // protocol violation, keep the byte
+                (false, b) => self.put(b),
+            }
+        }
+        res
+    }
+
+    fn put(&mut self, b: u8) {
+        self.escaped = false;
+        // Anything before the first FEND is a partial frame or line noise
+        if self.synced && self.buf.len() < Self::MAX_FRAME_LEN {
+            self.buf.push(b);
+        }
+    }
+
+    fn take(&mut self) -> Option<Frame> {
+        let buf = std::mem::take(&mut self.buf);
+        if buf.len() >= Self::MAX_FRAME_LEN {
+            log::warn!("dropping oversized KISS frame");
+            return None;
+        }
+        let (&typ, data) = buf.split_first()?; // empty frame is just back-to-back FENDs
+        Some(Frame {
+            port: typ >> 4,
+            command: typ & 0x0f,
+            data: data.to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let frame = Frame::data(1, vec![0x01, FEND, 0x02, FESC, 0x03]);
+        let bytes = frame.encode();
+        assert_eq!(
+            bytes,
+            vec![FEND, 0x10, 0x01, FESC, TFEND, 0x02, FESC, TFESC, 0x03, FEND]
+        );
+
+        // Feed it in pieces, preceded by line noise and an empty frame
+        let mut dec = Decoder::new();
+        assert_eq!(dec.push(&[0x55, 0x55, FEND, FEND]), vec![]);
+        assert_eq!(dec.push(&bytes[..4]), vec![]);
+        assert_eq!(dec.push(&bytes[4..]), vec![frame]);
+    }
+}
@@ -7,14 +7,26 @@ mod bm;
 mod brc;
 mod brc2023;
 pub mod aprs;
+mod afsk;
+mod agwpe;
+mod aprs_audio;
 mod aprs_is;
+mod aprs_is_server;
+mod aprs_replay;
+mod aprs_sim;
+mod aprs_tcp;
 mod aprs_tty;
+mod ax25;
+mod beacon;
 // mod aprslog;
 mod bmorg;
 mod clockpos;
 // mod jsonl;
 mod err;
+mod hdlc;
+mod igate;
 mod io;
+mod kiss;
 mod motion;
 mod svc;
 mod util;
@@ -23,6 +35,7 @@ mod webapi;
 use crate::err::Result;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
+const MB: u64 = 1024 * 1024;
 
     /// Location of data files directory
     // #[arg(long, value_name = "DIR", env, alias = "dataroot")]
@@ -44,6 +57,10 @@ struct Args {
     #[arg(long, value_name = "URL", env)]
     baudrate: Option<u16>,
 
+    /// TTY speaks KISS (binary AX.25 frames) rather than TNC2 text
+    #[arg(long, default_value_t = false, env)]
+    kiss: bool,
+
     /// Print available serial ports and exit
     #[arg(long, default_value_t = false)]
     print_ttys: bool,
@@ -59,18 +76,150 @@ struct Args {
     )]
     httpport: u16,
 
+    /// Serve APRS IS clients (e.g. Xastir, YAAC, APRSdroid) from this port
+    #[arg(long, value_name = "PORT", env)]
+    aprsis_port: Option<u16>,
+
     /// Serve web files from this directory
     #[arg(long, short, value_name = "URL", env, alias = "docroot")]
     wwwroot: Option<std::path::PathBuf>,
 
-    /// APRS IS server URL & port (e.g. rotate.aprs2.net:14580)
-    #[arg(long, value_name = "URL", env)]
-    aprsis: Option<String>,
+    /// APRS IS server URL & port (e.g. rotate.aprs2.net:14580), separate
+    /// multiple servers with commas to rotate between them
+    #[arg(long, value_name = "URL", env, value_delimiter = ',')]
+    aprsis: Vec<String>,
+
+    /// Our callsign, used to log into APRS IS
+    #[arg(long, value_name = "CALL", env, default_value = aprs_is::DEFAULT_CALLSIGN)]
+    callsign: String,
+
+    /// Receive APRS IS packets from this far from the city center
+    #[arg(long, value_name = "KM", env, default_value_t = aprs_is::DEFAULT_RADIUS_KM)]
+    aprsis_radius: f64,
+
+    /// Also receive APRS IS packets from stations with these callsign
+    /// prefixes, wherever they are
+    #[arg(long, value_name = "PREFIX", env, value_delimiter = ',')]
+    aprsis_prefixes: Vec<String>,
+
+    /// KISS TCP server URL & port, Dire Wolf's default if just the flag
+    /// is given
+    #[arg(long, value_name = "URL", env, num_args = 0..=1,
+        default_missing_value = aprs_tcp::DEFAULT_KISS_SERVER)]
+    kisstcp: Option<String>,
+
+    /// AGWPE server URL & port, Dire Wolf's default if just the flag is
+    /// given
+    #[arg(long, value_name = "URL", env, num_args = 0..=1,
+        default_missing_value = aprs_tcp::DEFAULT_AGWPE_SERVER)]
+    agwpe: Option<String>,
+
+    /// Demodulate AFSK1200 audio (16-bit PCM, WAV or raw) from this file,
+    /// pipe or stdin (-), e.g. from a radio through a sound card
+    #[arg(long, value_name = "FILE", env)]
+    audio: Option<String>,
+
+    /// Sample rate of raw audio, at least 8000
+    #[arg(
+        long,
+        value_name = "HZ",
+        env,
+        default_value_t = aprs_audio::DEFAULT_SAMPLE_RATE,
+        value_parser = clap::value_parser!(u32).range(aprs_audio::MIN_SAMPLE_RATE as i64..)
+    )]
+    audio_rate: u32,
+
+    /// Play back packets from this event log as if they were heard now, for
+    /// show only: they're not stored, answered or acked
+    #[arg(long, value_name = "FILENAME", env)]
+    replay: Option<PathBuf>,
+
+    /// How many times faster than recorded to play back
+    #[arg(long, value_name = "N", env, default_value_t = 1.)]
+    replay_speed: f64,
+
+    /// Longest pause between replayed packets, longer silences are cut short
+    #[arg(long, value_name = "SECS", env, default_value_t = 60)]
+    replay_max_pause: u64,
+
+    /// Make up this many art cars, bikes and people moving around the city,
+    /// for show only: they're not stored in the event log
+    #[arg(long, value_name = "N", env, default_value_t = 0)]
+    sim: usize,
+
+    /// Forward packets heard on RF to APRS IS (requires --aprsis and --callsign)
+    #[arg(long, default_value_t = false, env)]
+    igate: bool,
+
+    /// Answer WHERE, NEAR and WX queries messaged to our callsign over RF
+    /// (requires --callsign and --kiss or --kisstcp)
+    #[arg(long, default_value_t = false, env)]
+    answer_queries: bool,
+
+    /// Transmit our position beacon from this location (LAT,LNG) through
+    /// the KISS TNC (requires --callsign and --kiss or --kisstcp)
+    #[arg(long, value_name = "LAT,LNG", env, value_parser = parse_latlng)]
+    beacon_location: Option<util::geo::Point>,
+
+    /// Beacon symbol, table and code characters
+    #[arg(long, value_name = "SYMBOL", env, default_value = "/-")]
+    beacon_symbol: aprs::Symbol,
+
+    /// Beacon comment
+    #[arg(long, value_name = "TEXT", env, default_value = "")]
+    beacon_comment: String,
+
+    /// Beacon digipeater path
+    #[arg(long, value_name = "PATH", env, default_value = beacon::DEFAULT_PATH)]
+    beacon_path: String,
+
+    /// Minutes between beacons
+    #[arg(
+        long,
+        value_name = "MIN",
+        env,
+        default_value_t = 20,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    beacon_interval: u64,
+
+    /// Also announce this landmark as an APRS object along with our beacon,
+    /// as NAME@LAT,LNG[,SYMBOL[,COMMENT]]; can be repeated
+    #[arg(long = "object", value_name = "OBJECT")]
+    beacon_objects: Vec<beacon::Object>,
 
     /// Event log file
     #[arg(long, short = 'l', value_name = "FILENAME", env)]
     eventlog: Option<PathBuf>,
 
+    /// Keep the event log as JSON lines (jsonl) or in an SQLite database
+    /// (sqlite), which is faster to look up station history in
+    #[arg(long, value_name = "STORE", env, default_value = "jsonl")]
+    store: app::Backend,
+
+    /// Start a new event log segment every day (UTC), rather than keeping
+    /// it all in one file. JSON lines only.
+    #[arg(long, default_value_t = false, env)]
+    eventlog_daily: bool,
+
+    /// Start a new event log segment once it grows this big. JSON lines
+    /// only.
+    #[arg(long, value_name = "MB", env)]
+    eventlog_segment_mb: Option<u64>,
+
+    /// Delete event log records older than this many days
+    #[arg(long, value_name = "DAYS", env)]
+    eventlog_keep_days: Option<u64>,
+
+    /// Delete oldest event log segments to keep it all under this size.
+    /// JSON lines only.
+    #[arg(long, value_name = "MB", env)]
+    eventlog_max_mb: Option<u64>,
+
+    /// When to flush event log writes to disk: always, never (leave it to
+    /// the OS) or at most every this many seconds
+    #[arg(long, value_name = "POLICY", env, default_value = "always")]
+    eventlog_fsync: io::store::Fsync,
 }
 
 #[tokio::main]
@@ -89,14 +238,62 @@ pub async fn main() -> Result<()> {
     if args.print_ttys {
         app::print_ttys()
     } else {
+        let keep = args
+            .eventlog_keep_days
+            .map(|v| std::time::Duration::from_secs(v * 24 * 3600));
         app::run(
             args.httpport,
+            args.aprsis_port,
             args.wwwroot,
-            args.tty,
-            args.baudrate,
-            args.aprsis,
-            args.eventlog,
+            app::Inputs {
+                tty: args.tty,
+                baudrate: args.baudrate,
+                kiss: args.kiss,
+                aprsis_servers: args.aprsis,
+                callsign: args.callsign.clone(),
+                aprsis_radius_km: args.aprsis_radius,
+                aprsis_prefixes: args.aprsis_prefixes,
+                kiss_server: args.kisstcp,
+                agwpe_server: args.agwpe,
+                audio: args.audio,
+                audio_rate: args.audio_rate,
+                replay: args.replay,
+                replay_speed: args.replay_speed,
+                replay_max_pause: std::time::Duration::from_secs(args.replay_max_pause),
+                sim: args.sim,
+                igate: args.igate,
+                answer_queries: args.answer_queries,
+            },
+            args.beacon_location.map(|location| beacon::Config {
+                callsign: args.callsign.clone(),
+                location,
+                symbol: args.beacon_symbol,
+                comment: args.beacon_comment,
+                interval: std::time::Duration::from_secs(args.beacon_interval * 60),
+                path: args.beacon_path,
+                objects: args.beacon_objects,
+            }),
+            app::EventLog {
+                backend: args.store,
+                path: args.eventlog,
+                rotation: svc::jsonlog::Rotation {
+                    daily: args.eventlog_daily,
+                    max_segment_bytes: args.eventlog_segment_mb.map(|v| v * MB),
+                    max_age: keep,
+                    max_total_bytes: args.eventlog_max_mb.map(|v| v * MB),
+                },
+                fsync: args.eventlog_fsync,
+                max_age: keep,
+            },
         )
         .await
     }
 }
+
+fn parse_latlng(v: &str) -> Result<util::geo::Point> {
+    let bad = || err::Error::BadRequest(format!("bad location {:?}, expected LAT,LNG", v));
+    let (lat, lng) = v.split_once(',').ok_or_else(bad)?;
+    let lat = lat.trim().parse::<f64>().map_err(|_| bad())?;
+    let lng = lng.trim().parse::<f64>().map_err(|_| bad())?;
+    util::geo::Point::from_latlng((lat, lng))
+}
@@ -1 +1,2 @@
 pub mod jsonlog;
+pub mod sqlite;
@@ -1,18 +1,47 @@
 use crate::{
     err::{Error, Result},
-    util::time::{Timespan, Timestamp},
+    io::store::{Cursor, FromCallsign, Fsync, Record, Store},
+    util::time::{Duration, Timespan, Timestamp},
 };
 use serde::{de::DeserializeOwned, Serialize};
-use std::path::PathBuf;
+use std::{collections::VecDeque, io::SeekFrom, path::PathBuf};
 use tokio::{
     fs::{File, OpenOptions},
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter},
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader, BufWriter, Lines},
 };
 
+/// How far back in time a line can be from those before it, as clocks get
+/// adjusted; queries look this much beyond the span so as not to miss it
+const MAX_DISORDER: Duration = Duration::from_secs(5 * 60);
+
+/// Queries read through at most this much of the log before the span
+const SCAN_BYTES: u64 = 64 * 1024;
+
+/// When to start a new segment of the log and when to delete old ones.
+/// Rotated segments sit next to the log, named after it plus the time of
+/// their first record, e.g. `events.log.20230830T210000123Z`.
+#[derive(Debug, Clone, Default)]
+pub struct Rotation {
+    /// Start a new segment every UTC day
+    pub daily: bool,
+    /// Start a new segment once the current one is this big
+    pub max_segment_bytes: Option<u64>,
+    /// Delete segments that only have records older than this
+    pub max_age: Option<Duration>,
+    /// Delete oldest segments to keep the whole log under this size
+    pub max_total_bytes: Option<u64>,
+}
+
 pub struct JsonLog<T> {
     path: PathBuf,
+    rotation: Rotation,
+    fsync: Fsync,
+    last_fsync: Option<std::time::Instant>,
+    /// Records have been written since the last fsync
+    unsynced: bool,
     writer: Option<BufWriter<File>>,
-    reader: Option<BufReader<File>>,
+    /// Time of the first record in the current segment and its size
+    segment: Option<(Timestamp, u64)>,
     phantom: std::marker::PhantomData<T>,
 }
 
@@ -20,90 +49,346 @@ impl<T: std::fmt::Debug + Serialize + DeserializeOwned> JsonLog<T> {
     pub fn new(path: impl Into<PathBuf>) -> Self {
         let path = path.into();
         let phantom = std::marker::PhantomData;
-        let reader = None;
         let writer = None;
         Self {
             path,
-            phantom,
-            reader,
+            rotation: Rotation::default(),
+            fsync: Fsync::default(),
+            last_fsync: None,
+            unsynced: false,
             writer,
+            segment: None,
+            phantom,
         }
     }
 
+    pub fn with_rotation(mut self, rotation: Rotation) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_fsync(mut self, fsync: Fsync) -> Self {
+        self.fsync = fsync;
+        self
+    }
+
     pub async fn write(&mut self, ts: Timestamp, record: &T) -> Result<()> {
-        let writer = if let Some(writer) = &mut self.writer {
-            writer
-        } else {
-            let fd = OpenOptions::new() //File::options()
-                .create(true)
-                .append(true)
-                .open(&self.path)
-                .await
-                .map_err(|e| {
-                    Error::Other(format!(
-                        "{}: can't append, {}",
-                        self.path.to_string_lossy(),
-                        e
-                    ))
-                })?;
-            self.writer = Some(BufWriter::new(fd));
-            self.writer.as_mut().unwrap()
-        };
         let data = format!("{} {}\n", ts, serde_json::to_string(&record).unwrap()); // TODO: Fix this unwrap!
-        if let Err(e) = writer.write(data.as_bytes()).await {
+        if self.writer.is_none() {
+            self.open_writer(ts).await?;
+        }
+        if self.should_rotate(ts, data.len() as u64) {
+            self.rotate(ts).await?;
+        }
+        let writer = self.writer.as_mut().unwrap();
+        let res = match writer.write_all(data.as_bytes()).await {
+            Ok(()) => writer.flush().await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = res {
+            // Whatever part of the line got written is repaired on reopen
             self.close();
             Err(e)?
-        } else {
-            writer.flush().await?;
+        }
+        if let Some((_, size)) = &mut self.segment {
+            *size += data.len() as u64;
+        }
+        self.unsynced = true;
+        self.sync().await
+    }
+
+    /// Fsyncs records written since the last time if the policy says it's
+    /// time. Writing does this by itself, call it now and then too so that
+    /// the last records don't wait for another one to come along.
+    pub async fn sync(&mut self) -> Result<()> {
+        let due = match (self.fsync, self.last_fsync) {
+            _ if !self.unsynced => false,
+            (Fsync::Always, _) | (Fsync::Every(_), None) => true,
+            (Fsync::Every(interval), Some(last)) => last.elapsed() >= interval,
+            (Fsync::Never, _) => false,
+        };
+        if let (true, Some(writer)) = (due, &self.writer) {
+            writer.get_ref().sync_data().await?;
+            self.last_fsync = Some(std::time::Instant::now());
+            self.unsynced = false;
         }
         Ok(())
     }
 
-    pub async fn query(&mut self, span: Timespan) -> Result<Vec<(Timestamp, T)>> {
-        let reader = if let Some(reader) = &mut self.reader {
-            reader
+    /// Deals with the last line cut short by a crash or power loss: it gets
+    /// its newline if it's whole otherwise, or is cut off so that the next
+    /// record doesn't end up glued to it. Writing does this by itself, call
+    /// it to have the log fixed before reading it too.
+    pub async fn repair_tail(&self) -> Result<()> {
+        self.cut_torn_line().await.map_err(|e| {
+            Error::Other(format!(
+                "{}: can't repair, {}",
+                self.path.to_string_lossy(),
+                e
+            ))
+        })
+    }
+
+    async fn cut_torn_line(&self) -> Result<()> {
+        let mut fd = match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&self.path)
+            .await
+        {
+            Ok(fd) => fd,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => Err(e)?,
+        };
+        let len = fd.metadata().await?.len();
+        // Find where the last line starts
+        let mut start = len;
+        let mut buf = vec![0u8; 4096];
+        while start > 0 {
+            let n = buf.len().min(start as usize);
+            fd.seek(SeekFrom::Start(start - n as u64)).await?;
+            fd.read_exact(&mut buf[..n]).await?;
+            if start == len && buf[n - 1] == b'\n' {
+                return Ok(());
+            }
+            match buf[..n].iter().rposition(|c| *c == b'\n') {
+                Some(i) => {
+                    start = start - n as u64 + i as u64 + 1;
+                    break;
+                }
+                None => start -= n as u64,
+            }
+        }
+        if start == len {
+            return Ok(());
+        }
+
+        let mut tail = vec![0u8; (len - start) as usize];
+        fd.seek(SeekFrom::Start(start)).await?;
+        fd.read_exact(&mut tail).await?;
+        let tail = String::from_utf8_lossy(&tail).to_string();
+        if Self::parse_line(tail.clone()).is_ok() {
+            log::warn!("{}: adding missing newline", self.path.to_string_lossy());
+            fd.seek(SeekFrom::End(0)).await?;
+            fd.write_all(b"\n").await?;
         } else {
-            tokio::fs::create_dir_all(
-                self.path
-                    .parent()
-                    .ok_or(Error::msg("event log name must have a parent directory"))?,
-            )
+            log::warn!(
+                "{}: dropping torn last line {:?}",
+                self.path.to_string_lossy(),
+                tail
+            );
+            fd.set_len(start).await?;
+        }
+        fd.sync_all().await?;
+        Ok(())
+    }
+
+    async fn open_writer(&mut self, ts: Timestamp) -> Result<()> {
+        self.repair_tail().await?;
+        let fd = OpenOptions::new() //File::options()
+            .create(true)
+            .append(true)
+            .open(&self.path)
             .await
             .map_err(|e| {
                 Error::Other(format!(
-                    "{}: can't create, {}",
-                    self.path.to_string_lossy(),
-                    e
-                ))
-            })?;
-            let fd = File::open(&self.path).await.map_err(|e| {
-                Error::Other(format!(
-                    "{}: can't read, {}",
+                    "{}: can't append, {}",
                     self.path.to_string_lossy(),
                     e
                 ))
             })?;
+        let size = fd.metadata().await?.len();
+        // Segment started with its first record, which may be long ago
+        let start = match size {
+            0 => ts,
+            _ => self.first_timestamp().await.unwrap_or(ts),
+        };
+        self.segment = Some((start, size));
+        self.writer = Some(BufWriter::new(fd));
+        Ok(())
+    }
 
-            self.reader = Some(BufReader::new(fd));
-            self.reader.as_mut().unwrap()
+    async fn first_timestamp(&self) -> Option<Timestamp> {
+        let fd = File::open(&self.path).await.ok()?;
+        let line = BufReader::new(fd).lines().next_line().await.ok()??;
+        Self::split_line(line).ok().map(|(ts, _)| ts)
+    }
+
+    fn should_rotate(&self, ts: Timestamp, len: u64) -> bool {
+        let (start, size) = match self.segment {
+            Some(v) if v.1 > 0 => v,
+            _ => return false,
         };
-        let mut linenum = 0;
-        let mut res = Vec::new();
-        let mut lines = reader.lines();
-        while let Some(line) = lines.next_line().await? {
-            linenum += 1;
-            match Self::parse_line(line) {
-                Ok((ts, rec)) => {
-                    if span.includes(ts) {
-                        res.push((ts, rec))
-                    }
+        let day = |ts: Timestamp| ::time::OffsetDateTime::from(ts).date();
+        (self.rotation.daily && day(start) != day(ts))
+            || self
+                .rotation
+                .max_segment_bytes
+                .map(|max| size + len > max)
+                .unwrap_or(false)
+    }
+
+    /// Moves current segment aside, starts a new one and deletes whatever
+    /// the retention policy says is too old or too much
+    async fn rotate(&mut self, ts: Timestamp) -> Result<()> {
+        let (start, _) = self.segment.unwrap_or((ts, 0));
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush().await?;
+            if self.fsync != Fsync::Never {
+                writer.get_ref().sync_data().await?;
+            }
+        }
+        let rotated = self.segment_path(start);
+        log::info!(
+            "{}: rotating to {}",
+            self.path.to_string_lossy(),
+            rotated.to_string_lossy()
+        );
+        tokio::fs::rename(&self.path, &rotated).await?;
+        self.open_writer(ts).await?;
+
+        if let Some(max_age) = self.rotation.max_age {
+            self.remove_older(ts.saturating_sub(max_age)).await?;
+        }
+        if let Some(max_total) = self.rotation.max_total_bytes {
+            let segments = self.segments().await?;
+            let mut sizes = vec![];
+            for (_, path) in segments.iter() {
+                let size = tokio::fs::metadata(path).await.map(|m| m.len());
+                sizes.push(size.unwrap_or(0));
+            }
+            let mut total = sizes.iter().sum::<u64>() + self.segment.map(|v| v.1).unwrap_or(0);
+            for ((_, path), size) in segments.into_iter().zip(sizes) {
+                if total <= max_total {
+                    break;
                 }
-                Err(e) => log::error!("{}, line {}: {}", self.path.to_string_lossy(), linenum, e),
-            };
+                self.remove_segment(path).await;
+                total -= size;
+            }
+        }
+        Ok(())
+    }
+
+    /// Deletes rotated segments that only have records older than `ts`
+    async fn remove_older(&self, ts: Timestamp) -> Result<()> {
+        let segments = self.segments().await?;
+        // Segment ends where the next one starts, the last one where the
+        // current one does
+        let ends = segments
+            .iter()
+            .skip(1)
+            .map(|(start, _)| *start)
+            .chain(self.segment.map(|(start, _)| start));
+        for ((_, path), end) in segments.iter().zip(ends) {
+            if end > ts {
+                break;
+            }
+            self.remove_segment(path.clone()).await;
+        }
+        Ok(())
+    }
+
+    async fn remove_segment(&self, path: PathBuf) {
+        log::info!("{}: deleting old segment", path.to_string_lossy());
+        if let Err(e) = tokio::fs::remove_file(&path).await {
+            log::error!("{}: can't delete, {}", path.to_string_lossy(), e);
         }
+    }
+
+    fn segment_path(&self, start: Timestamp) -> PathBuf {
+        let dt = ::time::OffsetDateTime::from(start);
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(
+            ".{:04}{:02}{:02}T{:02}{:02}{:02}{:03}Z",
+            dt.year(),
+            dt.month() as u8,
+            dt.day(),
+            dt.hour(),
+            dt.minute(),
+            dt.second(),
+            dt.millisecond()
+        ));
+        self.path.with_file_name(name)
+    }
+
+    /// Returns rotated segments with times of their first records, oldest
+    /// first
+    async fn segments(&self) -> Result<Vec<(Timestamp, PathBuf)>> {
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let prefix = format!(
+            "{}.",
+            self.path.file_name().unwrap_or_default().to_string_lossy()
+        );
+        let mut res = vec![];
+        let mut entries = match tokio::fs::read_dir(&dir).await {
+            Ok(v) => v,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(res),
+            Err(e) => Err(e)?,
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let start = name
+                .strip_prefix(&prefix)
+                .and_then(|v| parse_segment_time(v).ok());
+            if let Some(start) = start {
+                res.push((start, entry.path()));
+            }
+        }
+        res.sort();
         Ok(res)
     }
 
+    /// Returns records within the span from all segments, oldest first
+    pub async fn query(&self, span: Timespan) -> Result<Vec<(Timestamp, T)>> {
+        let mut records = self.records(span).await?;
+        let mut res = Vec::new();
+        while let Some(v) = records.next().await? {
+            res.push(v);
+        }
+        Ok(res)
+    }
+
+    /// Same as [`Self::query`], but reads records one at a time as they're
+    /// asked for
+    pub async fn records(&self, span: Timespan) -> Result<Records<T>> {
+        tokio::fs::create_dir_all(
+            self.path
+                .parent()
+                .ok_or(Error::msg("event log name must have a parent directory"))?,
+        )
+        .await
+        .map_err(|e| {
+            Error::Other(format!(
+                "{}: can't create, {}",
+                self.path.to_string_lossy(),
+                e
+            ))
+        })?;
+
+        let segments = self.segments().await?;
+        let ends = segments
+            .iter()
+            .skip(1)
+            .map(|(start, _)| *start)
+            .chain(std::iter::once(Timestamp::MAX));
+        let mut paths = segments
+            .iter()
+            .zip(ends)
+            .filter(|((start, _), end)| *end > span.start() && *start < span.end())
+            .map(|((_, path), _)| path.clone())
+            .collect::<VecDeque<_>>();
+        paths.push_back(self.path.clone());
+        Ok(Records {
+            span,
+            paths,
+            reader: None,
+            phantom: std::marker::PhantomData,
+        })
+    }
+
     fn parse_line(line: String) -> Result<(Timestamp, T)> {
         let (ts, data) = Self::split_line(line)?;
         let rec = serde_json::from_str::<T>(&data)?;
@@ -121,8 +406,171 @@ impl<T: std::fmt::Debug + Serialize + DeserializeOwned> JsonLog<T> {
     }
 
     pub fn close(&mut self) {
-        self.reader = None;
         self.writer = None;
+        self.segment = None;
+    }
+}
+
+/// Parses segment name suffix, e.g. `20230830T210000123Z`
+fn parse_segment_time(s: &str) -> Result<Timestamp> {
+    let bad = || Error::Other(format!("bad segment time {:?}", s));
+    if s.len() != 19 || !s.is_char_boundary(8) || &s[8..9] != "T" || !s.ends_with('Z') {
+        return Err(bad());
+    }
+    let num = |from: usize, to: usize| s[from..to].parse::<u16>().map_err(|_| bad());
+    let ts = Timestamp::from_calendar_utc(
+        num(0, 4)?,
+        num(4, 6)? as u8,
+        num(6, 8)? as u8,
+        num(9, 11)? as u8,
+        num(11, 13)? as u8,
+        num(13, 15)? as u8,
+    )?;
+    ts.add(Duration::from_millis(num(15, 18)? as u64))
+}
+
+/// Records of a span read from the log one at a time, see
+/// [`JsonLog::records`]
+pub struct Records<T> {
+    span: Timespan,
+    /// Segments yet to read, oldest first
+    paths: VecDeque<PathBuf>,
+    reader: Option<Reader>,
+    phantom: std::marker::PhantomData<T>,
+}
+
+struct Reader {
+    path: PathBuf,
+    lines: Lines<BufReader<File>>,
+    /// Byte offset of the next line, for error messages
+    pos: u64,
+}
+
+impl<T: std::fmt::Debug + Serialize + DeserializeOwned> Records<T> {
+    /// Returns next record within the span, `None` when there are no more
+    pub async fn next(&mut self) -> Result<Option<(Timestamp, T)>> {
+        loop {
+            let reader = match &mut self.reader {
+                Some(reader) => reader,
+                None => match self.paths.pop_front() {
+                    Some(path) => {
+                        let from = self.span.start().saturating_sub(MAX_DISORDER);
+                        self.reader = Reader::open(path, from).await?;
+                        continue;
+                    }
+                    None => return Ok(None),
+                },
+            };
+            let line = match reader.lines.next_line().await? {
+                Some(line) => line,
+                None => {
+                    self.reader = None;
+                    continue;
+                }
+            };
+            let pos = reader.pos;
+            reader.pos += line.len() as u64 + 1;
+            match JsonLog::<T>::parse_line(line) {
+                Ok((ts, rec)) if self.span.includes(ts) => return Ok(Some((ts, rec))),
+                Ok((ts, _)) => {
+                    if ts >= self.span.end().saturating_add(MAX_DISORDER) {
+                        // Rest of the segment is later still
+                        self.reader = None;
+                    }
+                }
+                Err(e) => log::error!("{}, byte {}: {}", reader.path.to_string_lossy(), pos, e),
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Cursor for Records<Record> {
+    async fn next(&mut self) -> Result<Option<(Timestamp, Record)>> {
+        Records::next(self).await
+    }
+}
+
+#[async_trait::async_trait]
+impl Store for JsonLog<Record> {
+    async fn append(&mut self, ts: Timestamp, record: &Record) -> Result<()> {
+        self.write(ts, record).await
+    }
+
+    async fn records(&self, span: Timespan) -> Result<Box<dyn Cursor>> {
+        Ok(Box::new(JsonLog::records(self, span).await?))
+    }
+
+    async fn records_from(&self, callsign: &str, span: Timespan) -> Result<Box<dyn Cursor>> {
+        let records = JsonLog::records(self, span).await?;
+        Ok(Box::new(FromCallsign::new(Box::new(records), callsign)))
+    }
+
+    /// Deletes whole segments, so some older records may be left
+    async fn compact(&mut self, ts: Timestamp) -> Result<()> {
+        self.remove_older(ts).await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.sync().await
+    }
+}
+
+impl Reader {
+    /// Opens log segment at the first line at or after `ts`, returns `None`
+    /// if there's no such file (nothing written since the last rotation or
+    /// ever)
+    async fn open(path: PathBuf, ts: Timestamp) -> Result<Option<Self>> {
+        let mut fd = match File::open(&path).await {
+            Ok(fd) => fd,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(Error::Other(format!(
+                    "{}: can't read, {}",
+                    path.to_string_lossy(),
+                    e
+                )))
+            }
+        };
+        let pos = find_line(&mut fd, ts).await?;
+        fd.seek(SeekFrom::Start(pos)).await?;
+        let lines = BufReader::new(fd).lines();
+        Ok(Some(Self { path, lines, pos }))
+    }
+}
+
+/// Binary searches for a line starting at or before the first line at or
+/// after `ts`, so that it's only a short scan away. Lines are written in
+/// time order, mostly; see [`MAX_DISORDER`].
+async fn find_line(fd: &mut File, ts: Timestamp) -> Result<u64> {
+    let (mut lo, mut hi) = (0, fd.metadata().await?.len());
+    while hi - lo > SCAN_BYTES {
+        let mid = lo + (hi - lo) / 2;
+        match line_after(fd, mid).await? {
+            Some((start, line_ts)) if start < hi && line_ts < ts => lo = start,
+            _ => hi = mid,
+        }
+    }
+    Ok(lo)
+}
+
+/// Returns start and timestamp of the first whole line after `pos`
+async fn line_after(fd: &mut File, pos: u64) -> Result<Option<(u64, Timestamp)>> {
+    fd.seek(SeekFrom::Start(pos)).await?;
+    let mut reader = BufReader::new(fd);
+    let mut buf = vec![];
+    let mut start = pos + reader.read_until(b'\n', &mut buf).await? as u64;
+    loop {
+        buf.clear();
+        let n = reader.read_until(b'\n', &mut buf).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        let line = String::from_utf8_lossy(&buf).to_string();
+        if let Ok((ts, _)) = JsonLog::<serde_json::Value>::split_line(line) {
+            return Ok(Some((start, ts)));
+        }
+        start += n as u64;
     }
 }
 
@@ -160,3 +608,151 @@ async fn try_write(path: &Path, rx: &mut mpsc::Receiver<Event>) -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rotation() {
+        let dir = std::env::temp_dir().join(format!("lpkiosk-rotation-{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("events.log");
+        let mut log = JsonLog::<u32>::new(&path).with_rotation(Rotation {
+            daily: true,
+            max_segment_bytes: Some(200),
+            max_age: Some(Duration::from_secs(2 * 24 * 3600)),
+            max_total_bytes: None,
+        });
+
+        // Five days, ten records a day; eight records fit in a segment, so
+        // each day starts one at :00 and another at :08
+        let t0 = Timestamp::from_calendar_utc(2023, 8, 27, 12, 0, 0).unwrap();
+        let at = |day: u64, n: u64| {
+            t0.add(Duration::from_secs(day * 24 * 3600 + n * 60))
+                .unwrap()
+        };
+        for day in 0..5 {
+            for n in 0..10 {
+                log.write(at(day, n), &((day * 10 + n) as u32))
+                    .await
+                    .unwrap();
+            }
+        }
+        let segments = log.segments().await.unwrap();
+        assert!(segments.len() > 2, "{:?}", segments);
+        for (_, path) in segments.iter() {
+            assert!(std::fs::metadata(path).unwrap().len() <= 200);
+        }
+        // Last rotation was at day 4 :08, so all before day 2 :08 is gone
+        assert_eq!(segments[0].0, at(2, 8));
+
+        // Query sees through segments
+        let span = Timespan::new(at(2, 9), at(3, 5));
+        let res = log.query(span).await.unwrap();
+        let expected = (29..35).collect::<Vec<u32>>();
+        assert_eq!(res.into_iter().map(|v| v.1).collect::<Vec<_>>(), expected);
+        let res = log.query(Timespan::MAX).await.unwrap();
+        assert_eq!(res.len(), 22);
+
+        // Total size cap
+        let mut log = JsonLog::<u32>::new(&path).with_rotation(Rotation {
+            daily: true,
+            max_total_bytes: Some(300),
+            ..Default::default()
+        });
+        log.write(at(5, 0), &50).await.unwrap();
+        let res = log.query(Timespan::MAX).await.unwrap();
+        assert!(res.len() < 15, "{}", res.len());
+        assert_eq!(res.last(), Some(&(at(5, 0), 50)));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_records() {
+        let path = std::env::temp_dir().join(format!("lpkiosk-records-{}.log", std::process::id()));
+        let t0 = Timestamp::from_calendar_utc(2023, 8, 30, 0, 0, 0).unwrap();
+        let at = |secs: u64| t0.add(Duration::from_secs(secs)).unwrap();
+        // A day, a record a second, and one from a clock that was behind
+        let mut data = String::new();
+        for n in 0..24 * 3600 {
+            data.push_str(&format!("{} {}\n", at(n), n));
+            if n == 40030 {
+                data.push_str(&format!("{} {}\n", at(40005), 0));
+            }
+        }
+        std::fs::write(&path, data).unwrap();
+        let log = JsonLog::<u64>::new(&path);
+
+        // Seeks close to where the span is
+        let mut fd = File::open(&path).await.unwrap();
+        let pos = find_line(&mut fd, at(50000)).await.unwrap();
+        let (start, ts) = line_after(&mut fd, pos).await.unwrap().unwrap();
+        assert!(ts <= at(50000), "{}", ts);
+        assert!(at(50000).duration_between(ts) < Duration::from_secs(3600));
+        assert!(start > 0);
+
+        let span = Timespan::new(at(39950), at(40010));
+        let mut records = log.records(span).await.unwrap();
+        let mut res = vec![];
+        while let Some((ts, n)) = records.next().await.unwrap() {
+            assert!(span.includes(ts));
+            res.push(n);
+        }
+        let mut expected = (39950..40010).collect::<Vec<u64>>();
+        expected.push(0);
+        assert_eq!(res, expected);
+
+        assert_eq!(log.query(Timespan::MAX).await.unwrap().len(), 24 * 3600 + 1);
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_torn_tail() {
+        let path = std::env::temp_dir().join(format!("lpkiosk-torn-{}.log", std::process::id()));
+        let t0 = Timestamp::from_calendar_utc(2023, 8, 30, 0, 0, 0).unwrap();
+        let at = |secs: u64| t0.add(Duration::from_secs(secs)).unwrap();
+
+        // Power went out in the middle of a line
+        std::fs::write(&path, format!("{} [1]\n{} [2,", at(1), at(2))).unwrap();
+        let mut log =
+            JsonLog::<Vec<u32>>::new(&path).with_fsync(Fsync::Every(Duration::from_secs(5)));
+        log.write(at(3), &vec![3]).await.unwrap();
+        let expected = vec![(at(1), vec![1]), (at(3), vec![3])];
+        assert_eq!(log.query(Timespan::MAX).await.unwrap(), expected);
+        // Asking again gets the same
+        assert_eq!(log.query(Timespan::MAX).await.unwrap(), expected);
+
+        // ...or right before the newline
+        std::fs::write(&path, format!("{} [1]\n{} [2]", at(1), at(2))).unwrap();
+        let mut log = JsonLog::<Vec<u32>>::new(&path);
+        log.write(at(3), &vec![3]).await.unwrap();
+        let res = log.query(Timespan::MAX).await.unwrap();
+        assert_eq!(
+            res.iter().map(|v| v.1[0]).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+
+        // ...and it's fixed before reading too
+        std::fs::write(&path, format!("{} [1]\n{} [2,", at(1), at(2))).unwrap();
+        let log = JsonLog::<Vec<u32>>::new(&path);
+        log.repair_tail().await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            format!("{} [1]\n", at(1))
+        );
+
+        // ...or nothing has ever made it to disk
+        std::fs::write(&path, "2023-08-").unwrap();
+        let mut log = JsonLog::<Vec<u32>>::new(&path);
+        log.write(at(3), &vec![3]).await.unwrap();
+        assert_eq!(
+            log.query(Timespan::MAX).await.unwrap(),
+            vec![(at(3), vec![3])]
+        );
+
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,323 @@
+use crate::{
+    err::{Error, Result},
+    io::store::{Cursor, Fsync, Record, Store},
+    util::time::{Timespan, Timestamp},
+};
+use rusqlite::{params, Connection};
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+/// Records read per query, cursors fetch more as they run out
+const PAGE_SIZE: usize = 256;
+
+static SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS records (
+        id INTEGER PRIMARY KEY,
+        ts INTEGER NOT NULL,
+        callsign TEXT,
+        record TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS records_ts ON records (ts);
+    CREATE INDEX IF NOT EXISTS records_callsign_ts ON records (callsign, ts);
+";
+
+/// Event store in an SQLite database, indexed by time and by callsign;
+/// records are kept as the same JSON as in [`crate::svc::jsonlog::JsonLog`]
+pub struct SqliteStore {
+    path: PathBuf,
+    conn: Arc<Mutex<Connection>>,
+    fsync: Fsync,
+    last_fsync: Option<std::time::Instant>,
+    /// Records have been appended since the last checkpoint
+    unsynced: bool,
+}
+
+impl SqliteStore {
+    pub fn open(path: impl Into<PathBuf>, fsync: Fsync) -> Result<Self> {
+        let path = path.into();
+        if let Some(dir) = path.parent().filter(|v| !v.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| {
+                Error::Other(format!("{}: can't create, {}", dir.to_string_lossy(), e))
+            })?;
+        }
+        let conn =
+            Connection::open(&path).map_err(|e| Error::LoadFile(path.clone(), e.to_string()))?;
+        // Readers don't block the writer. With NORMAL, commits only reach
+        // the disk at checkpoints, which flush() does as often as asked to.
+        let synchronous = match fsync {
+            Fsync::Always => "FULL",
+            Fsync::Every(_) => "NORMAL",
+            Fsync::Never => "OFF",
+        };
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", synchronous)?;
+        conn.execute_batch(SCHEMA)?;
+        let conn = Arc::new(Mutex::new(conn));
+        Ok(Self {
+            path,
+            conn,
+            fsync,
+            last_fsync: None,
+            unsynced: false,
+        })
+    }
+
+    /// Runs `f` with the connection off the async runtime
+    async fn with_conn<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Connection) -> Result<R> + Send + 'static,
+    ) -> Result<R> {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.lock().map_err(|e| Error::Other(e.to_string()))?;
+            f(&conn)
+        })
+        .await
+        .map_err(|e| Error::Other(e.to_string()))?
+    }
+
+    fn cursor(&self, callsign: Option<String>, span: Timespan) -> SqliteCursor {
+        SqliteCursor {
+            conn: self.conn.clone(),
+            callsign,
+            span,
+            after: None,
+            page: vec![],
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Store for SqliteStore {
+    async fn append(&mut self, ts: Timestamp, record: &Record) -> Result<()> {
+        let data = serde_json::to_string(record)?;
+        let callsign = record.callsign().map(|v| v.to_ascii_uppercase());
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO records (ts, callsign, record) VALUES (?1, ?2, ?3)",
+                params![ts.as_unix_millis() as i64, callsign, data],
+            )?;
+            Ok(())
+        })
+        .await
+        .map_err(|e| {
+            Error::Other(format!(
+                "{}: can't append, {}",
+                self.path.to_string_lossy(),
+                e
+            ))
+        })?;
+        self.unsynced = true;
+        self.flush().await
+    }
+
+    async fn records(&self, span: Timespan) -> Result<Box<dyn Cursor>> {
+        Ok(Box::new(self.cursor(None, span)))
+    }
+
+    async fn records_from(&self, callsign: &str, span: Timespan) -> Result<Box<dyn Cursor>> {
+        let callsign = callsign.to_ascii_uppercase();
+        Ok(Box::new(self.cursor(Some(callsign), span)))
+    }
+
+    async fn compact(&mut self, ts: Timestamp) -> Result<()> {
+        let path = self.path.clone();
+        self.with_conn(move |conn| {
+            let cnt = conn.execute(
+                "DELETE FROM records WHERE ts < ?1",
+                params![ts.as_unix_millis() as i64],
+            )?;
+            if cnt > 0 {
+                log::info!("{}: deleted {} old records", path.to_string_lossy(), cnt);
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        let due = match (self.fsync, self.last_fsync) {
+            (Fsync::Every(_), None) => self.unsynced,
+            (Fsync::Every(interval), Some(last)) => self.unsynced && last.elapsed() >= interval,
+            // Taken care of on every commit, or never
+            (Fsync::Always | Fsync::Never, _) => false,
+        };
+        if due {
+            self.with_conn(|conn| {
+                // Syncs the WAL, then the database
+                conn.query_row("PRAGMA wal_checkpoint(PASSIVE)", [], |_| Ok(()))?;
+                Ok(())
+            })
+            .await?;
+            self.last_fsync = Some(std::time::Instant::now());
+            self.unsynced = false;
+        }
+        Ok(())
+    }
+}
+
+/// Pages through records in `(ts, id)` order, so that it neither holds the
+/// connection between calls nor loads everything at once
+pub struct SqliteCursor {
+    conn: Arc<Mutex<Connection>>,
+    callsign: Option<String>,
+    span: Timespan,
+    /// `(ts, id)` of the last record returned
+    after: Option<(i64, i64)>,
+    /// Next records, last first
+    page: Vec<(i64, i64, String)>,
+}
+
+impl SqliteCursor {
+    fn fetch(
+        conn: &Connection,
+        callsign: Option<&str>,
+        span: Timespan,
+        after: Option<(i64, i64)>,
+    ) -> Result<Vec<(i64, i64, String)>> {
+        let (after_ts, after_id) = after.unwrap_or((i64::MIN, i64::MIN));
+        let mut stmt = conn.prepare_cached(
+            "SELECT ts, id, record FROM records
+            WHERE ts >= ?1 AND ts < ?2 AND (?3 IS NULL OR callsign = ?3)
+                AND (ts > ?4 OR (ts = ?4 AND id > ?5))
+            ORDER BY ts, id LIMIT ?6",
+        )?;
+        let rows = stmt.query_map(
+            params![
+                span.start().as_unix_millis().min(i64::MAX as u64) as i64,
+                span.end().as_unix_millis().min(i64::MAX as u64) as i64,
+                callsign,
+                after_ts,
+                after_id,
+                PAGE_SIZE as i64,
+            ],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+        let mut res = rows.collect::<std::result::Result<Vec<_>, _>>()?;
+        res.reverse();
+        Ok(res)
+    }
+}
+
+#[async_trait::async_trait]
+impl Cursor for SqliteCursor {
+    async fn next(&mut self) -> Result<Option<(Timestamp, Record)>> {
+        loop {
+            if self.page.is_empty() {
+                let conn = self.conn.clone();
+                let (callsign, span, after) = (self.callsign.clone(), self.span, self.after);
+                self.page = tokio::task::spawn_blocking(move || {
+                    let conn = conn.lock().map_err(|e| Error::Other(e.to_string()))?;
+                    Self::fetch(&conn, callsign.as_deref(), span, after)
+                })
+                .await
+                .map_err(|e| Error::Other(e.to_string()))??;
+            }
+            let (ts, id, data) = match self.page.pop() {
+                Some(v) => v,
+                None => return Ok(None),
+            };
+            self.after = Some((ts, id));
+            match serde_json::from_str::<Record>(&data) {
+                Ok(rec) => return Ok(Some((Timestamp::from_unix_millis(ts as u64), rec))),
+                Err(e) => log::error!("record {}: {}", id, e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::time::Duration;
+
+    fn packet(data: &str) -> Record {
+        Record::AprsPacket {
+            data: data.into(),
+            source: Some("/dev/ttyUSB0".into()),
+            path: vec![],
+            digipeater: None,
+        }
+    }
+
+    async fn collect(mut cursor: Box<dyn Cursor>) -> Vec<(Timestamp, String)> {
+        let mut res = vec![];
+        while let Some((ts, rec)) = cursor.next().await.unwrap() {
+            match rec {
+                Record::AprsPacket { data, .. } => res.push((ts, data)),
+            }
+        }
+        res
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_store() {
+        let path = std::env::temp_dir().join(format!("lpkiosk-store-{}.db", std::process::id()));
+        std::fs::remove_file(&path).ok();
+        let mut store = SqliteStore::open(&path, Fsync::Never).unwrap();
+        let t0 = Timestamp::from_calendar_utc(2023, 8, 30, 0, 0, 0).unwrap();
+        let at = |secs: u64| t0.add(Duration::from_secs(secs)).unwrap();
+
+        // More than a page, two stations taking turns
+        for n in 0..1000 {
+            let call = if n % 2 == 0 { "DUCK" } else { "goose-9" };
+            let data = format!("{}>APRS:>{}", call, n);
+            store.append(at(n), &packet(&data)).await.unwrap();
+        }
+
+        let res = collect(
+            store
+                .records(Timespan::new(at(100), at(700)))
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(res.len(), 600);
+        assert_eq!(res[0], (at(100), "DUCK>APRS:>100".to_string()));
+        assert_eq!(res[599], (at(699), "goose-9>APRS:>699".to_string()));
+
+        let res = collect(
+            store
+                .records_from("GOOSE-9", Timespan::new(at(0), at(1000)))
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(res.len(), 500);
+        assert!(res.iter().all(|(_, data)| data.starts_with("goose-9>")));
+        assert!(res.windows(2).all(|v| v[0].0 < v[1].0));
+
+        store.compact(at(900)).await.unwrap();
+        let cnt: i64 = store
+            .with_conn(|conn| {
+                Ok(conn.query_row("SELECT COUNT(*) FROM records", [], |row| row.get(0))?)
+            })
+            .await
+            .unwrap();
+        assert_eq!(cnt, 100);
+
+        // Survives reopening
+        drop(store);
+        let mut store = SqliteStore::open(&path, Fsync::Every(Duration::from_secs(60))).unwrap();
+        let res = collect(store.records(Timespan::MAX).await.unwrap()).await;
+        assert_eq!(res.len(), 100);
+
+        // First append is checkpointed, the next one waits for its time
+        store
+            .append(at(1000), &packet("DUCK>APRS:>1000"))
+            .await
+            .unwrap();
+        assert!(!store.unsynced);
+        store
+            .append(at(1001), &packet("DUCK>APRS:>1001"))
+            .await
+            .unwrap();
+        assert!(store.unsynced);
+        store.flush().await.unwrap();
+        assert!(store.unsynced);
+
+        std::fs::remove_file(&path).ok();
+    }
+}
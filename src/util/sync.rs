@@ -22,3 +22,11 @@ where
     }
 }
 
+/// Receives from the channel if there is one, waits forever otherwise. Handy
+/// for optional channels in `select!`.
+pub async fn recv_if_some<T>(rx: &mut Option<&mut tokio::sync::mpsc::Receiver<T>>) -> Option<T> {
+    match rx {
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
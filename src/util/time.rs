@@ -166,7 +166,7 @@ impl Timestamp {
     pub fn sub(self, rhs: Duration) -> Result<Self> {
         let from = self.as_unix_millis();
         let diff = rhs.as_millis();
-        if from as u128 > diff {
+        if diff > from as u128 {
             Err(Error::OutOfRange {
                 msg: format!(
                     "subtracting {} ms from {} underflows timestamp",
@@ -182,7 +182,7 @@ impl Timestamp {
     pub const fn saturating_sub(self, rhs: Duration) -> Self {
         let from = self.as_unix_millis();
         let diff = rhs.as_millis();
-        if from as u128 > diff {
+        if diff > from as u128 {
             Self::MIN
         } else {
             Self::from_unix_millis(from - diff as u64)
@@ -425,6 +425,13 @@ mod tests {
         let ts2 = Timestamp::from_calendar_utc(2022, 2, 2, 2, 3, 2).unwrap();
         // let dur = Duration::days(1);
         assert_eq!(ts2.millis_between(ts1), 60000);
+        assert_eq!(ts2.sub(Duration::from_secs(60)).unwrap(), ts1);
+        assert_eq!(ts2.saturating_sub(Duration::from_secs(60)), ts1);
+        assert!(ts1.sub(Duration::from_secs(u64::MAX / 1000)).is_err());
+        assert_eq!(
+            ts1.saturating_sub(Duration::from_secs(u64::MAX / 1000)),
+            Timestamp::MIN
+        );
     }
 
     #[test]
@@ -1,8 +1,9 @@
 use crate::{
+    aprs,
     err::{Error, Result},
     io,
 };
-use actix_web::{self, get, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
+use actix_web::{self, get, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
 use tokio::sync::mpsc;
 
 pub type JsonQuery = JsonValue;
@@ -43,43 +44,73 @@ async fn get_view(
         }
     };
 
+    ask_backend(&req, |res_tx| io::user::Event::ViewRequest(q, res_tx)).await
+}
+
+#[post("/api/v0/messages")]
+async fn post_message(req: HttpRequest, msg: web::Json<io::user::NewMessage>) -> impl Responder {
+    ask_backend(&req, |res_tx| {
+        io::user::Event::SendMessage(msg.into_inner(), res_tx)
+    })
+    .await
+}
+
+/// Passes the request on to the server and turns what it says, or doesn't,
+/// into a response
+async fn ask_backend<T: serde::Serialize>(
+    req: &HttpRequest,
+    evt: impl FnOnce(tokio::sync::oneshot::Sender<Result<T>>) -> io::user::Event,
+) -> HttpResponse {
     let back = req.app_data::<mpsc::Sender<io::user::Event>>().unwrap();
-    let (res_tx, res_rx) = tokio::sync::oneshot::channel::<Result<io::user::View>>();
-    let evt = io::user::Event::ViewRequest(q, res_tx);
-    if let Err(_) = back.try_send(evt) {
+    let (res_tx, res_rx) = tokio::sync::oneshot::channel::<Result<T>>();
+    if back.try_send(evt(res_tx)).is_err() {
         return HttpResponse::ServiceUnavailable().json(json!({
-        "status": "server busy",
-        "message": "We're experiencing high request volume, please try again later.",
+            "status": "server busy",
+            "message": "We're experiencing high request volume, please try again later.",
         }));
     }
     match res_rx.await {
-        Ok(Ok(view)) => HttpResponse::Ok().json(append(
-            serde_json::to_value(view).unwrap(),
+        Ok(Ok(res)) => HttpResponse::Ok().json(append(
+            serde_json::to_value(res).unwrap(),
             json!({
                 "status": "ok",
             }),
         )),
         Ok(Err(Error::BadRequest(e))) => HttpResponse::BadRequest().json(json!({
-        "status": "bad request",
-        "message": e,
+            "status": "bad request",
+            "message": e,
         })),
         Ok(Err(e)) => {
             log::error!("internal server error: {}", e);
             HttpResponse::InternalServerError().json(json!({
-            "status": "internal server error",
-            "message": "something went wrong on our side, please try again later",
+                "status": "internal server error",
+                "message": "something went wrong on our side, please try again later",
             }))
         }
         Err(_) => {
             log::error!("internal server error: failed to get response from backend");
             HttpResponse::InternalServerError().json(json!({
-            "status": "internal server error",
-            "message": "something went wrong on our side, please try again later",
+                "status": "internal server error",
+                "message": "something went wrong on our side, please try again later",
             }))
         }
     }
 }
 
+#[get("/api/v0/symbols/{id}.svg")]
+async fn get_symbol(id: web::Path<String>) -> impl Responder {
+    match aprs::Symbol::from_id(&id) {
+        Ok(sym) => HttpResponse::Ok()
+            .content_type("image/svg+xml")
+            .insert_header(("Cache-Control", "max-age=86400"))
+            .body(sym.icon_svg()),
+        Err(e) => HttpResponse::BadRequest().json(json!({
+            "status": "bad request",
+            "message": e.to_string(),
+        })),
+    }
+}
+
 pub async fn run(
     port: u16,
     www_root: Option<std::path::PathBuf>,
@@ -96,7 +127,9 @@ pub async fn run(
         let app = App::new()
             .app_data(backend.clone())
             .wrap(actix_web::middleware::Logger::new("%a %r %s"))
-            .service(get_view);
+            .service(get_view)
+            .service(post_message)
+            .service(get_symbol);
         let app = if let Some(dir) = &www_root {
             app.service(
                 actix_files::Files::new("/", dir)